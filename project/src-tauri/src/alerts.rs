@@ -0,0 +1,426 @@
+// 告警规则：用户定义 "延迟 > 150 ms 持续 30 秒" 之类的条件
+//
+// 每条规则的状态机：
+// - 条件连续满足 duration_secs 后触发，距上次触发不足 cooldown_secs 时继续等待
+// - 触发后数值越过 threshold 加上回差（hysteresis）才解除，避免在阈值附近来回抖动
+// 触发和解除都会产生一个 AlertEvent，由后台线程发出 alert 事件和桌面通知。
+
+use crate::settings::ConfigFile;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+pub const CONFIG_FILE_NAME: &str = "alerts.json";
+pub const ALERT_EVENT: &str = "alert";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    // ms
+    Latency,
+    // %
+    PacketLoss,
+    // ms
+    Jitter,
+    // KB/s
+    DownloadSpeed,
+    UploadSpeed,
+}
+
+impl Metric {
+    fn label(self) -> &'static str {
+        match self {
+            Metric::Latency => "延迟",
+            Metric::PacketLoss => "丢包率",
+            Metric::Jitter => "抖动",
+            Metric::DownloadSpeed => "下载速度",
+            Metric::UploadSpeed => "上传速度",
+        }
+    }
+
+    fn unit(self) -> &'static str {
+        match self {
+            Metric::Latency | Metric::Jitter => "ms",
+            Metric::PacketLoss => "%",
+            Metric::DownloadSpeed | Metric::UploadSpeed => "KB/s",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    #[serde(rename = ">")]
+    Above,
+    #[serde(rename = "<")]
+    Below,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AlertRule {
+    // 唯一名称，用于通知标题和事件
+    pub name: String,
+    pub metric: Metric,
+    pub op: Comparison,
+    pub threshold: f64,
+    // 条件需要连续满足的秒数，0 表示立即触发
+    #[serde(default)]
+    pub duration_secs: u64,
+    // 解除时需要越过阈值的额外幅度，单位与 metric 相同
+    #[serde(default)]
+    pub hysteresis: f64,
+    // 两次触发之间的最短间隔
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+    // 只在下载 + 上传速度不低于该值（KB/s）时判断，用于 "有传输时下载 < 100 KB/s"
+    #[serde(default)]
+    pub min_activity: Option<f64>,
+    #[serde(default = "default_notify")]
+    pub notify: bool,
+}
+
+// 持续时间和冷却时间的上限：1 天
+const MAX_RULE_SECS: u64 = 24 * 3600;
+
+fn default_cooldown_secs() -> u64 {
+    300
+}
+
+fn default_notify() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AlertConfig {
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+}
+
+impl ConfigFile for AlertConfig {
+    const FILE_NAME: &'static str = CONFIG_FILE_NAME;
+    const LABEL: &'static str = "告警规则";
+
+    fn validate(&self) -> Result<(), String> {
+        let mut names = HashSet::new();
+        for rule in &self.rules {
+            if rule.name.trim().is_empty() {
+                return Err("告警规则名称不能为空".to_string());
+            }
+            if !names.insert(rule.name.as_str()) {
+                return Err(format!("告警规则名称重复: {}", rule.name));
+            }
+            if !rule.threshold.is_finite() || !rule.hysteresis.is_finite() || rule.hysteresis < 0.0 {
+                return Err(format!("告警规则 {} 的阈值或回差无效", rule.name));
+            }
+            if rule.duration_secs > MAX_RULE_SECS || rule.cooldown_secs > MAX_RULE_SECS {
+                return Err(format!("告警规则 {} 的持续时间和冷却时间不能超过 {} 秒", rule.name, MAX_RULE_SECS));
+            }
+        }
+        Ok(())
+    }
+}
+
+// 一次计算得到的各项指标
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Metrics {
+    pub latency: f64,
+    pub packet_loss: f64,
+    pub jitter: f64,
+    pub download_speed: f64,
+    pub upload_speed: f64,
+}
+
+impl Metrics {
+    fn get(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::Latency => self.latency,
+            Metric::PacketLoss => self.packet_loss,
+            Metric::Jitter => self.jitter,
+            Metric::DownloadSpeed => self.download_speed,
+            Metric::UploadSpeed => self.upload_speed,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Firing,
+    Cleared,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AlertEvent {
+    pub rule: String,
+    pub state: AlertState,
+    pub metric: Metric,
+    pub value: f64,
+    pub threshold: f64,
+    // Unix 毫秒
+    pub timestamp: u64,
+    pub message: String,
+    #[serde(skip)]
+    pub notify: bool,
+}
+
+#[derive(Default)]
+struct RuleState {
+    // 条件开始连续满足的时间
+    pending_since: Option<u64>,
+    firing: bool,
+    last_fired: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct AlertEngine {
+    states: HashMap<String, RuleState>,
+}
+
+impl AlertEngine {
+    // now 为 Unix 毫秒，返回本次状态发生变化的规则
+    pub fn evaluate(&mut self, config: &AlertConfig, metrics: &Metrics, now: u64) -> Vec<AlertEvent> {
+        // 删除或改名的规则不再保留状态
+        self.states.retain(|name, _| config.rules.iter().any(|rule| rule.name == *name));
+
+        let mut events = Vec::new();
+        for rule in &config.rules {
+            let state = self.states.entry(rule.name.clone()).or_default();
+            let value = metrics.get(rule.metric);
+            let active = rule
+                .min_activity
+                .is_none_or(|min| metrics.download_speed + metrics.upload_speed >= min);
+
+            let breached = active
+                && match rule.op {
+                    Comparison::Above => value > rule.threshold,
+                    Comparison::Below => value < rule.threshold,
+                };
+
+            if state.firing {
+                let cleared = !active
+                    || match rule.op {
+                        Comparison::Above => value <= rule.threshold - rule.hysteresis,
+                        Comparison::Below => value >= rule.threshold + rule.hysteresis,
+                    };
+                if cleared {
+                    state.firing = false;
+                    state.pending_since = None;
+                    events.push(event(rule, AlertState::Cleared, value, now));
+                }
+                continue;
+            }
+
+            if !breached {
+                state.pending_since = None;
+                continue;
+            }
+
+            let since = *state.pending_since.get_or_insert(now);
+            let held = now.saturating_sub(since) >= rule.duration_secs.saturating_mul(1000);
+            let cooled = state
+                .last_fired
+                .is_none_or(|last| now.saturating_sub(last) >= rule.cooldown_secs.saturating_mul(1000));
+
+            if held && cooled {
+                state.firing = true;
+                state.last_fired = Some(now);
+                events.push(event(rule, AlertState::Firing, value, now));
+            }
+        }
+
+        events
+    }
+}
+
+fn event(rule: &AlertRule, state: AlertState, value: f64, timestamp: u64) -> AlertEvent {
+    let op = match rule.op {
+        Comparison::Above => ">",
+        Comparison::Below => "<",
+    };
+    let unit = rule.metric.unit();
+    let message = match state {
+        AlertState::Firing => format!(
+            "{} {:.1} {}（{} {} {}）",
+            rule.metric.label(), value, unit, op, rule.threshold, unit
+        ),
+        AlertState::Cleared => format!("{}已恢复到 {:.1} {}", rule.metric.label(), value, unit),
+    };
+
+    AlertEvent {
+        rule: rule.name.clone(),
+        state,
+        metric: rule.metric,
+        value,
+        threshold: rule.threshold,
+        timestamp,
+        message,
+        notify: rule.notify,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(metric: Metric, op: Comparison, threshold: f64) -> AlertRule {
+        AlertRule {
+            name: "rule".to_string(),
+            metric,
+            op,
+            threshold,
+            duration_secs: 0,
+            hysteresis: 0.0,
+            cooldown_secs: 0,
+            min_activity: None,
+            notify: true,
+        }
+    }
+
+    fn config(rule: AlertRule) -> AlertConfig {
+        AlertConfig { rules: vec![rule] }
+    }
+
+    fn latency(value: f64) -> Metrics {
+        Metrics { latency: value, ..Default::default() }
+    }
+
+    fn states(engine: &mut AlertEngine, config: &AlertConfig, metrics: &Metrics, now_secs: u64) -> Vec<AlertState> {
+        engine.evaluate(config, metrics, now_secs * 1000).iter().map(|e| e.state).collect()
+    }
+
+    #[test]
+    fn fires_only_after_condition_holds_for_duration() {
+        let config = config(AlertRule { duration_secs: 30, ..rule(Metric::Latency, Comparison::Above, 150.0) });
+        let mut engine = AlertEngine::default();
+
+        assert!(states(&mut engine, &config, &latency(200.0), 0).is_empty());
+        assert!(states(&mut engine, &config, &latency(200.0), 29).is_empty());
+        assert_eq!(states(&mut engine, &config, &latency(200.0), 30), [AlertState::Firing]);
+        // 持续触发期间不重复产生事件
+        assert!(states(&mut engine, &config, &latency(200.0), 31).is_empty());
+    }
+
+    #[test]
+    fn interruption_restarts_duration() {
+        let config = config(AlertRule { duration_secs: 30, ..rule(Metric::Latency, Comparison::Above, 150.0) });
+        let mut engine = AlertEngine::default();
+
+        states(&mut engine, &config, &latency(200.0), 0);
+        assert!(states(&mut engine, &config, &latency(100.0), 20).is_empty());
+        assert!(states(&mut engine, &config, &latency(200.0), 40).is_empty());
+        assert_eq!(states(&mut engine, &config, &latency(200.0), 70), [AlertState::Firing]);
+    }
+
+    #[test]
+    fn clears_only_past_hysteresis() {
+        let config = config(AlertRule { hysteresis: 20.0, ..rule(Metric::Latency, Comparison::Above, 150.0) });
+        let mut engine = AlertEngine::default();
+
+        assert_eq!(states(&mut engine, &config, &latency(160.0), 0), [AlertState::Firing]);
+        assert!(states(&mut engine, &config, &latency(140.0), 1).is_empty());
+        assert_eq!(states(&mut engine, &config, &latency(130.0), 2), [AlertState::Cleared]);
+    }
+
+    #[test]
+    fn below_rule_clears_above_threshold_plus_hysteresis() {
+        let config = config(AlertRule { hysteresis: 50.0, ..rule(Metric::DownloadSpeed, Comparison::Below, 100.0) });
+        let mut engine = AlertEngine::default();
+        let speed = |v: f64| Metrics { download_speed: v, ..Default::default() };
+
+        assert_eq!(states(&mut engine, &config, &speed(80.0), 0), [AlertState::Firing]);
+        assert!(states(&mut engine, &config, &speed(120.0), 1).is_empty());
+        assert_eq!(states(&mut engine, &config, &speed(150.0), 2), [AlertState::Cleared]);
+    }
+
+    #[test]
+    fn cooldown_delays_refiring() {
+        let config = config(AlertRule { cooldown_secs: 300, ..rule(Metric::Latency, Comparison::Above, 150.0) });
+        let mut engine = AlertEngine::default();
+
+        assert_eq!(states(&mut engine, &config, &latency(200.0), 0), [AlertState::Firing]);
+        assert_eq!(states(&mut engine, &config, &latency(100.0), 10), [AlertState::Cleared]);
+        assert!(states(&mut engine, &config, &latency(200.0), 20).is_empty());
+        assert!(states(&mut engine, &config, &latency(200.0), 299).is_empty());
+        assert_eq!(states(&mut engine, &config, &latency(200.0), 300), [AlertState::Firing]);
+    }
+
+    #[test]
+    fn min_activity_gates_evaluation_and_clears_when_idle() {
+        let config = config(AlertRule { min_activity: Some(50.0), ..rule(Metric::DownloadSpeed, Comparison::Below, 100.0) });
+        let mut engine = AlertEngine::default();
+        let speeds = |down: f64, up: f64| Metrics { download_speed: down, upload_speed: up, ..Default::default() };
+
+        // 空闲时下载速度低不算告警
+        assert!(states(&mut engine, &config, &speeds(10.0, 5.0), 0).is_empty());
+        // 上传占了活动量
+        assert_eq!(states(&mut engine, &config, &speeds(10.0, 60.0), 1), [AlertState::Firing]);
+        // 传输停止后解除
+        assert_eq!(states(&mut engine, &config, &speeds(0.0, 0.0), 2), [AlertState::Cleared]);
+    }
+
+    #[test]
+    fn removed_rules_drop_their_state() {
+        let firing = config(rule(Metric::Latency, Comparison::Above, 150.0));
+        let mut engine = AlertEngine::default();
+        assert_eq!(states(&mut engine, &firing, &latency(200.0), 0), [AlertState::Firing]);
+
+        states(&mut engine, &AlertConfig::default(), &latency(200.0), 1);
+        // 重新添加后作为新规则再次触发
+        assert_eq!(states(&mut engine, &firing, &latency(200.0), 2), [AlertState::Firing]);
+    }
+
+    #[test]
+    fn event_message_includes_value_and_threshold() {
+        let config = config(rule(Metric::Latency, Comparison::Above, 150.0));
+        let events = AlertEngine::default().evaluate(&config, &latency(212.34), 5000);
+        assert_eq!(events[0].message, "延迟 212.3 ms（> 150 ms）");
+        assert_eq!(events[0].timestamp, 5000);
+    }
+
+    #[test]
+    fn validate_rejects_duplicate_names_and_bad_numbers() {
+        let ok = config(rule(Metric::Latency, Comparison::Above, 150.0));
+        assert!(ok.validate().is_ok());
+
+        let duplicate = AlertConfig { rules: vec![ok.rules[0].clone(), ok.rules[0].clone()] };
+        assert!(duplicate.validate().is_err());
+
+        let negative = config(AlertRule { hysteresis: -1.0, ..ok.rules[0].clone() });
+        assert!(negative.validate().is_err());
+
+        let nan = config(AlertRule { threshold: f64::NAN, ..ok.rules[0].clone() });
+        assert!(nan.validate().is_err());
+    }
+
+    #[test]
+    fn validate_limits_duration_and_cooldown_to_a_day() {
+        let base = rule(Metric::Latency, Comparison::Above, 150.0);
+        let day = config(AlertRule { duration_secs: MAX_RULE_SECS, cooldown_secs: MAX_RULE_SECS, ..base.clone() });
+        assert!(day.validate().is_ok());
+
+        let duration = config(AlertRule { duration_secs: MAX_RULE_SECS + 1, ..base.clone() });
+        assert!(duration.validate().is_err());
+        let cooldown = config(AlertRule { cooldown_secs: u64::MAX, ..base });
+        assert!(cooldown.validate().is_err());
+    }
+
+    // 未经校验的配置也不会因乘法溢出而 panic
+    #[test]
+    fn huge_duration_and_cooldown_do_not_overflow() {
+        let config = config(AlertRule {
+            duration_secs: u64::MAX,
+            cooldown_secs: u64::MAX,
+            ..rule(Metric::Latency, Comparison::Above, 150.0)
+        });
+        let mut engine = AlertEngine::default();
+
+        assert!(states(&mut engine, &config, &latency(200.0), 0).is_empty());
+        assert!(states(&mut engine, &config, &latency(200.0), 365 * 24 * 3600).is_empty());
+    }
+
+    #[test]
+    fn rule_defaults_apply_when_fields_are_missing() {
+        let rule: AlertRule =
+            serde_json::from_str(r#"{"name":"slow","metric":"latency","op":">","threshold":150}"#).unwrap();
+        assert_eq!((rule.duration_secs, rule.cooldown_secs, rule.notify), (0, 300, true));
+        assert_eq!(rule.min_activity, None);
+    }
+}
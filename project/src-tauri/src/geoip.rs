@@ -0,0 +1,176 @@
+// 离线 IP 地理位置：从本地 MaxMind GeoLite2 / DB-IP 的 .mmdb 文件查询城市、地区、国家、时区和 ASN
+//
+// 在线接口有频率限制，只返回 IP 的接口没有地理位置，myip.ipip.net 也只识别十个城市。
+// 配置数据库后，接口结果中缺少的字段按数据库补齐。城市库和 ASN 库是分开的文件，可以同时配置，
+// 按顺序查询，先查到的字段优先。数据库文件更新（修改时间变化）后自动重新加载。
+
+use crate::public_ip::IPInfo;
+use maxminddb::{MaxMindDBError, Reader};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard};
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+// 名称使用的语言，与在线接口一致
+const LANGUAGE: &str = "en";
+
+#[derive(Default, Debug)]
+pub struct GeoRecord {
+    pub city: Option<String>,
+    pub region: Option<String>,
+    pub country: Option<String>,
+    pub timezone: Option<String>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+}
+
+impl GeoRecord {
+    // 已有的字段不覆盖
+    fn merge(&mut self, other: GeoRecord) {
+        self.city = self.city.take().or(other.city);
+        self.region = self.region.take().or(other.region);
+        self.country = self.country.take().or(other.country);
+        self.timezone = self.timezone.take().or(other.timezone);
+        self.asn = self.asn.or(other.asn);
+        self.as_org = self.as_org.take().or(other.as_org);
+    }
+}
+
+// 同时兼容城市库（GeoIP2 / GeoLite2-City、DB-IP City）和 ASN 库的记录格式，缺少的字段为 None
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Record {
+    city: Option<Names>,
+    subdivisions: Option<Vec<Names>>,
+    country: Option<Names>,
+    location: Option<Location>,
+    autonomous_system_number: Option<u32>,
+    autonomous_system_organization: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Names {
+    names: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Location {
+    time_zone: Option<String>,
+}
+
+impl Names {
+    fn name(&self) -> Option<String> {
+        self.names.get(LANGUAGE).filter(|name| !name.is_empty()).cloned()
+    }
+}
+
+impl From<Record> for GeoRecord {
+    fn from(record: Record) -> Self {
+        GeoRecord {
+            city: record.city.as_ref().and_then(Names::name),
+            region: record.subdivisions.as_ref().and_then(|s| s.first()).and_then(Names::name),
+            country: record.country.as_ref().and_then(Names::name),
+            timezone: record.location.and_then(|l| l.time_zone).filter(|tz| !tz.is_empty()),
+            asn: record.autonomous_system_number,
+            as_org: record.autonomous_system_organization.filter(|org| !org.is_empty()),
+        }
+    }
+}
+
+struct Database {
+    modified: Option<SystemTime>,
+    // 打开失败时记录错误，文件变化前不再重试，避免每次查询都输出警告
+    reader: Result<Arc<Reader<Vec<u8>>>, String>,
+}
+
+static DATABASES: LazyLock<Mutex<HashMap<PathBuf, Database>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn lock_databases() -> MutexGuard<'static, HashMap<PathBuf, Database>> {
+    match DATABASES.lock() {
+        Ok(databases) => databases,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+fn open(path: &PathBuf) -> Option<Arc<Reader<Vec<u8>>>> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+    {
+        let databases = lock_databases();
+        if let Some(db) = databases.get(path).filter(|db| db.modified == modified) {
+            return db.reader.as_ref().ok().cloned();
+        }
+    }
+
+    // 读取整个文件可能较慢，在锁外打开，避免阻塞其它数据库的查询
+    let reader = Reader::open_readfile(path).map(Arc::new).map_err(|e| e.to_string());
+    match &reader {
+        Ok(reader) => debug!(
+            "Loaded geo database {} ({}, built {})",
+            path.display(),
+            reader.metadata.database_type,
+            reader.metadata.build_epoch
+        ),
+        Err(e) => warn!("Failed to open geo database {}: {}", path.display(), e),
+    }
+
+    let result = reader.as_ref().ok().cloned();
+    // 其它线程可能同时打开了同一文件，以后写入的为准，结果相同
+    lock_databases().insert(path.clone(), Database { modified, reader });
+    result
+}
+
+// 按顺序查询各数据库并合并；读取整个文件，需在阻塞线程中调用
+pub fn lookup(paths: &[String], ip: IpAddr) -> GeoRecord {
+    let mut result = GeoRecord::default();
+    for path in paths.iter().map(PathBuf::from) {
+        let Some(reader) = open(&path) else { continue };
+        match reader.lookup::<Record>(ip) {
+            Ok(record) => result.merge(record.into()),
+            Err(MaxMindDBError::AddressNotFoundError(_)) => {}
+            Err(e) => warn!("Geo lookup of {} in {} failed: {}", ip, path.display(), e),
+        }
+    }
+
+    // 移除设置中已删除的数据库，释放内存
+    if let Ok(mut databases) = DATABASES.lock() {
+        databases.retain(|path, _| paths.iter().any(|p| path.as_path() == Path::new(p)));
+    }
+    result
+}
+
+fn is_missing(value: &str) -> bool {
+    value.is_empty() || value == "Unknown"
+}
+
+// 补齐接口结果中缺少的字段；城市与 ip-api.com 一致，优先使用州 / 省
+pub fn fill(info: &mut IPInfo, paths: &[String]) {
+    let Ok(ip) = info.ip.parse::<IpAddr>() else { return };
+    let record = lookup(paths, ip);
+
+    if is_missing(&info.city) {
+        if let Some(city) = record.region.clone().or(record.city) {
+            info.city = city;
+        }
+    }
+    if let (true, Some(region)) = (info.region.is_empty(), record.region) {
+        info.region = region;
+    }
+    if let (true, Some(country)) = (info.country.is_empty(), record.country) {
+        info.country = country;
+    }
+    if let (true, Some(timezone)) = (info.timezone.is_empty(), record.timezone) {
+        info.timezone = timezone;
+    }
+    if info.asn.is_none() {
+        info.asn = record.asn;
+    }
+    if let (true, Some(as_org)) = (info.as_org.is_empty(), record.as_org) {
+        info.as_org = as_org;
+    }
+}
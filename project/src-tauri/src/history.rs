@@ -0,0 +1,290 @@
+// 流量历史：把后台线程每秒计算的样本写入本地 SQLite，供图表查询
+//
+// 按分辨率分三张表，写入时同时更新：
+// - 1 秒：保留 1 小时
+// - 1 分钟：保留 1 天
+// - 1 小时：保留 1 年
+// 分钟和小时表按桶累计平均值和字节数，查询时不需要再聚合。
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const FILE_NAME: &str = "history.sqlite3";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    #[serde(rename = "1s")]
+    Second,
+    #[serde(rename = "1m")]
+    Minute,
+    #[serde(rename = "1h")]
+    Hour,
+    // 选择仍覆盖查询起点的最细分辨率
+    #[serde(rename = "auto")]
+    Auto,
+}
+
+struct Tier {
+    table: &'static str,
+    bucket_secs: u64,
+    retention_secs: u64,
+}
+
+const TIERS: [Tier; 3] = [
+    Tier { table: "samples_1s", bucket_secs: 1, retention_secs: 3600 },
+    Tier { table: "samples_1m", bucket_secs: 60, retention_secs: 24 * 3600 },
+    Tier { table: "samples_1h", bucket_secs: 3600, retention_secs: 365 * 24 * 3600 },
+];
+
+impl Resolution {
+    fn tier(self, from_secs: u64, now_secs: u64) -> &'static Tier {
+        match self {
+            Resolution::Second => &TIERS[0],
+            Resolution::Minute => &TIERS[1],
+            Resolution::Hour => &TIERS[2],
+            Resolution::Auto => TIERS
+                .iter()
+                .find(|tier| from_secs + tier.retention_secs >= now_secs)
+                .unwrap_or(&TIERS[2]),
+        }
+    }
+}
+
+// 查询范围，Unix 毫秒，与 NetworkStats::timestamp 一致
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct HistoryRange {
+    pub from: u64,
+    pub to: u64,
+}
+
+// 一次写入的样本，速度为 KB/s，字节数为本次间隔内计入总速率的网卡流量
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sample {
+    pub timestamp: u64,
+    pub download_speed: f64,
+    pub upload_speed: f64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub latency: f64,
+    pub packet_loss: f64,
+}
+
+// 查询结果，timestamp 为桶的起始时间（Unix 毫秒），其余为桶内平均值
+#[derive(Serialize, Clone, Debug)]
+pub struct HistoryPoint {
+    pub timestamp: u64,
+    pub download_speed: f64,
+    pub upload_speed: f64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub latency: f64,
+    pub packet_loss: f64,
+}
+
+pub struct HistoryStore {
+    conn: Connection,
+    // 上次清理过期数据所在的分钟，每分钟清理一次
+    last_prune_minute: u64,
+}
+
+impl HistoryStore {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("创建数据目录失败: {}", e))?;
+        }
+        let conn = Connection::open(path).map_err(|e| format!("打开历史数据库失败: {}", e))?;
+        Self::init(conn)
+    }
+
+    fn init(conn: Connection) -> Result<Self, String> {
+        // WAL + NORMAL：每秒一次小事务，不需要每次都 fsync
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .and_then(|_| conn.pragma_update(None, "synchronous", "NORMAL"))
+            .map_err(|e| format!("配置历史数据库失败: {}", e))?;
+
+        for tier in &TIERS {
+            conn.execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+                    ts INTEGER PRIMARY KEY,
+                    count INTEGER NOT NULL,
+                    download_speed REAL NOT NULL,
+                    upload_speed REAL NOT NULL,
+                    rx_bytes INTEGER NOT NULL,
+                    tx_bytes INTEGER NOT NULL,
+                    latency REAL NOT NULL,
+                    packet_loss REAL NOT NULL
+                )",
+                tier.table
+            ))
+            .map_err(|e| format!("创建历史数据表失败: {}", e))?;
+        }
+
+        Ok(HistoryStore { conn, last_prune_minute: 0 })
+    }
+
+    pub fn record(&mut self, sample: &Sample) -> Result<(), String> {
+        let now_secs = sample.timestamp / 1000;
+        let tx = self.conn.transaction().map_err(|e| format!("写入历史数据失败: {}", e))?;
+
+        for tier in &TIERS {
+            // 同一个桶再次写入时按 count 更新平均值，字节数直接累加
+            tx.execute(
+                &format!(
+                    "INSERT INTO {table} (ts, count, download_speed, upload_speed, rx_bytes, tx_bytes, latency, packet_loss)
+                     VALUES (?1, 1, ?2, ?3, ?4, ?5, ?6, ?7)
+                     ON CONFLICT(ts) DO UPDATE SET
+                        count = count + 1,
+                        download_speed = download_speed + (excluded.download_speed - download_speed) / (count + 1),
+                        upload_speed = upload_speed + (excluded.upload_speed - upload_speed) / (count + 1),
+                        rx_bytes = rx_bytes + excluded.rx_bytes,
+                        tx_bytes = tx_bytes + excluded.tx_bytes,
+                        latency = latency + (excluded.latency - latency) / (count + 1),
+                        packet_loss = packet_loss + (excluded.packet_loss - packet_loss) / (count + 1)",
+                    table = tier.table
+                ),
+                params![
+                    (now_secs / tier.bucket_secs * tier.bucket_secs) as i64,
+                    sample.download_speed,
+                    sample.upload_speed,
+                    sample.rx_bytes as i64,
+                    sample.tx_bytes as i64,
+                    sample.latency,
+                    sample.packet_loss,
+                ],
+            )
+            .map_err(|e| format!("写入历史数据失败: {}", e))?;
+        }
+
+        let minute = now_secs / 60;
+        if minute != self.last_prune_minute {
+            for tier in &TIERS {
+                tx.execute(
+                    &format!("DELETE FROM {} WHERE ts < ?1", tier.table),
+                    params![now_secs.saturating_sub(tier.retention_secs) as i64],
+                )
+                .map_err(|e| format!("清理历史数据失败: {}", e))?;
+            }
+            self.last_prune_minute = minute;
+        }
+
+        tx.commit().map_err(|e| format!("写入历史数据失败: {}", e))
+    }
+
+    pub fn query(&self, range: HistoryRange, resolution: Resolution, now_ms: u64) -> Result<Vec<HistoryPoint>, String> {
+        let tier = resolution.tier(range.from / 1000, now_ms / 1000);
+
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT ts, download_speed, upload_speed, rx_bytes, tx_bytes, latency, packet_loss
+                 FROM {} WHERE ts >= ?1 AND ts <= ?2 ORDER BY ts",
+                tier.table
+            ))
+            .map_err(|e| format!("查询历史数据失败: {}", e))?;
+
+        // 起点向下取整到桶边界，包含起点所在的桶
+        let from = range.from / 1000 / tier.bucket_secs * tier.bucket_secs;
+        let rows = stmt
+            .query_map(params![from as i64, (range.to / 1000) as i64], |row| {
+                Ok(HistoryPoint {
+                    timestamp: row.get::<_, i64>(0)? as u64 * 1000,
+                    download_speed: row.get(1)?,
+                    upload_speed: row.get(2)?,
+                    rx_bytes: row.get::<_, i64>(3)? as u64,
+                    tx_bytes: row.get::<_, i64>(4)? as u64,
+                    latency: row.get(5)?,
+                    packet_loss: row.get(6)?,
+                })
+            })
+            .map_err(|e| format!("查询历史数据失败: {}", e))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("查询历史数据失败: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR_MS: u64 = 3600 * 1000;
+    // 任意选取的整点时间，便于按桶对齐
+    const NOW_MS: u64 = 1_700_000_000 / 3600 * 3600 * 1000;
+
+    fn store() -> HistoryStore {
+        HistoryStore::init(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn sample(timestamp: u64, download_speed: f64, rx_bytes: u64) -> Sample {
+        Sample { timestamp, download_speed, rx_bytes, latency: 10.0, ..Default::default() }
+    }
+
+    #[test]
+    fn auto_resolution_picks_finest_tier_covering_start() {
+        let now = NOW_MS / 1000;
+        assert_eq!(Resolution::Auto.tier(now - 600, now).table, "samples_1s");
+        assert_eq!(Resolution::Auto.tier(now - 3600, now).table, "samples_1s");
+        assert_eq!(Resolution::Auto.tier(now - 3601, now).table, "samples_1m");
+        assert_eq!(Resolution::Auto.tier(now - 2 * 24 * 3600, now).table, "samples_1h");
+        // 超出所有保留期时使用最粗的分辨率
+        assert_eq!(Resolution::Auto.tier(0, now).table, "samples_1h");
+        // 显式指定时不考虑起点
+        assert_eq!(Resolution::Second.tier(0, now).table, "samples_1s");
+    }
+
+    #[test]
+    fn minute_tier_averages_speeds_and_sums_bytes() {
+        let mut store = store();
+        store.record(&sample(NOW_MS, 100.0, 1000)).unwrap();
+        store.record(&sample(NOW_MS + 1000, 200.0, 2000)).unwrap();
+        store.record(&sample(NOW_MS + 2000, 600.0, 3000)).unwrap();
+        store.record(&sample(NOW_MS + 60_000, 50.0, 500)).unwrap();
+
+        let range = HistoryRange { from: NOW_MS, to: NOW_MS + 120_000 };
+        let points = store.query(range, Resolution::Minute, NOW_MS + 120_000).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].timestamp, NOW_MS);
+        assert!((points[0].download_speed - 300.0).abs() < 1e-9);
+        assert_eq!(points[0].rx_bytes, 6000);
+        assert!((points[0].latency - 10.0).abs() < 1e-9);
+        assert_eq!((points[1].timestamp, points[1].rx_bytes), (NOW_MS + 60_000, 500));
+
+        let seconds = store.query(range, Resolution::Second, NOW_MS + 120_000).unwrap();
+        assert_eq!(seconds.len(), 4);
+    }
+
+    #[test]
+    fn query_includes_bucket_containing_start() {
+        let mut store = store();
+        store.record(&sample(NOW_MS + 10_000, 100.0, 1000)).unwrap();
+
+        // 起点落在桶中间
+        let range = HistoryRange { from: NOW_MS + 30_000, to: NOW_MS + 60_000 };
+        let points = store.query(range, Resolution::Minute, NOW_MS + 60_000).unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].timestamp, NOW_MS);
+    }
+
+    #[test]
+    fn record_prunes_expired_rows_per_tier() {
+        let mut store = store();
+        store.record(&sample(NOW_MS, 100.0, 1000)).unwrap();
+        store.record(&sample(NOW_MS + 2 * HOUR_MS, 100.0, 1000)).unwrap();
+
+        let range = HistoryRange { from: NOW_MS, to: NOW_MS + 2 * HOUR_MS };
+        let now = NOW_MS + 2 * HOUR_MS;
+        // 1 秒表只保留 1 小时，分钟和小时表仍有两条
+        assert_eq!(store.query(range, Resolution::Second, now).unwrap().len(), 1);
+        assert_eq!(store.query(range, Resolution::Minute, now).unwrap().len(), 2);
+        assert_eq!(store.query(range, Resolution::Hour, now).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn resolution_deserializes_from_short_names() {
+        let parse = |s: &str| serde_json::from_str::<Resolution>(s).unwrap();
+        assert_eq!(parse("\"1s\""), Resolution::Second);
+        assert_eq!(parse("\"1h\""), Resolution::Hour);
+        assert_eq!(parse("\"auto\""), Resolution::Auto);
+    }
+}
@@ -0,0 +1,141 @@
+// 用户选择哪些网卡计入顶部的下载/上传速度
+//
+// 例如同时运行 WireGuard 和物理网卡时，隧道流量会在两块网卡上各算一次，
+// 可以只统计物理网卡，或只统计承载默认路由的网卡。选择结果保存在应用配置目录。
+
+use crate::interfaces::InterfaceSnapshot;
+use crate::settings::ConfigFile;
+use serde::{Deserialize, Serialize};
+
+pub const FILE_NAME: &str = "interface_filter.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum InterfaceFilter {
+    // 所有在线的非回环网卡（旧行为）
+    #[default]
+    All,
+    // 只统计列表中的网卡
+    Include { interfaces: Vec<String> },
+    // 统计除列表外的所有在线非回环网卡
+    Exclude { interfaces: Vec<String> },
+    // 只统计承载默认路由的网卡
+    DefaultRoute,
+}
+
+fn matches(list: &[String], snapshot: &InterfaceSnapshot) -> bool {
+    list.iter().any(|entry| snapshot.matches(entry))
+}
+
+impl InterfaceFilter {
+    // default_route_interface 为 None 时 DefaultRoute 等同于 All
+    pub fn counts(&self, snapshot: &InterfaceSnapshot, default_route_interface: Option<&str>) -> bool {
+        if !snapshot.counts_towards_total() {
+            return false;
+        }
+
+        match self {
+            InterfaceFilter::All => true,
+            InterfaceFilter::Include { interfaces } => matches(interfaces, snapshot),
+            InterfaceFilter::Exclude { interfaces } => !matches(interfaces, snapshot),
+            InterfaceFilter::DefaultRoute => default_route_interface.is_none_or(|id| id == snapshot.id),
+        }
+    }
+
+    // 返回供 interfaces::compute_rates 使用的判断函数
+    // 默认路由的网卡不在本次采集结果中时退回到统计所有网卡，避免显示 0：尚未找到默认路由，
+    // 或 PowerShell 回退采集时网卡 id 为 "ps:<ifIndex>"，与路由表给出的 LUID 对不上
+    pub fn counter<'a>(
+        &'a self,
+        snapshots: &[InterfaceSnapshot],
+        default_route_interface: Option<&'a str>,
+    ) -> impl Fn(&InterfaceSnapshot) -> bool + 'a {
+        let default_route = default_route_interface.filter(|id| snapshots.iter().any(|s| s.id == *id));
+        move |snapshot| self.counts(snapshot, default_route)
+    }
+}
+
+impl ConfigFile for InterfaceFilter {
+    const FILE_NAME: &'static str = FILE_NAME;
+    const LABEL: &'static str = "网卡筛选";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(id: &str, name: &str, alias: &str) -> InterfaceSnapshot {
+        InterfaceSnapshot {
+            id: id.to_string(),
+            name: name.to_string(),
+            alias: alias.to_string(),
+            if_type: "ethernet",
+            mac: String::new(),
+            link_speed: 0,
+            up: true,
+            loopback: false,
+            rx_bytes: 0,
+            tx_bytes: 0,
+        }
+    }
+
+    fn counted(filter: &InterfaceFilter, snapshots: &[InterfaceSnapshot], route: Option<&str>) -> Vec<String> {
+        let counts = filter.counter(snapshots, route);
+        snapshots.iter().filter(|s| counts(s)).map(|s| s.id.clone()).collect()
+    }
+
+    #[test]
+    fn include_and_exclude_match_id_name_or_alias() {
+        let snapshots = [
+            snapshot("0006001000000000", "Intel(R) Ethernet", "Ethernet"),
+            snapshot("0047000000000000", "WireGuard Tunnel", "wg0"),
+        ];
+
+        let include = InterfaceFilter::Include { interfaces: vec!["Ethernet".into()] };
+        assert_eq!(counted(&include, &snapshots, None), ["0006001000000000"]);
+
+        let exclude = InterfaceFilter::Exclude { interfaces: vec!["WireGuard Tunnel".into()] };
+        assert_eq!(counted(&exclude, &snapshots, None), ["0006001000000000"]);
+
+        let include_id = InterfaceFilter::Include { interfaces: vec!["0047000000000000".into()] };
+        assert_eq!(counted(&include_id, &snapshots, None), ["0047000000000000"]);
+    }
+
+    #[test]
+    fn loopback_and_down_interfaces_are_never_counted() {
+        let mut lo = snapshot("lo", "lo", "lo");
+        lo.loopback = true;
+        let mut down = snapshot("eth1", "eth1", "eth1");
+        down.up = false;
+        let snapshots = [lo, down, snapshot("eth0", "eth0", "eth0")];
+
+        let include = InterfaceFilter::Include { interfaces: vec!["lo".into(), "eth1".into()] };
+        assert!(counted(&include, &snapshots, None).is_empty());
+        assert_eq!(counted(&InterfaceFilter::All, &snapshots, None), ["eth0"]);
+    }
+
+    #[test]
+    fn default_route_counts_only_the_route_interface() {
+        let snapshots = [snapshot("eth0", "eth0", "eth0"), snapshot("wg0", "wg0", "wg0")];
+        assert_eq!(counted(&InterfaceFilter::DefaultRoute, &snapshots, Some("wg0")), ["wg0"]);
+    }
+
+    #[test]
+    fn default_route_falls_back_to_all_when_route_interface_is_unknown() {
+        // PowerShell 回退采集的 id 与路由表中的 LUID 不一致
+        let snapshots = [snapshot("ps:12", "Intel(R) Ethernet", "Ethernet"), snapshot("ps:7", "Wi-Fi", "Wi-Fi")];
+        assert_eq!(
+            counted(&InterfaceFilter::DefaultRoute, &snapshots, Some("0006001000000000")),
+            ["ps:12", "ps:7"]
+        );
+        assert_eq!(counted(&InterfaceFilter::DefaultRoute, &snapshots, None), ["ps:12", "ps:7"]);
+    }
+
+    #[test]
+    fn filter_round_trips_through_json() {
+        let filter = InterfaceFilter::Exclude { interfaces: vec!["wg0".into()] };
+        let text = serde_json::to_string(&filter).unwrap();
+        assert_eq!(text, r#"{"mode":"exclude","interfaces":["wg0"]}"#);
+        assert_eq!(serde_json::from_str::<InterfaceFilter>(&text).unwrap(), filter);
+    }
+}
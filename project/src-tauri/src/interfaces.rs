@@ -0,0 +1,361 @@
+// 网卡级别的流量统计
+//
+// 各平台的采集函数返回每个网卡的 InterfaceSnapshot（累计字节数），
+// 后台线程用相邻两次快照按网卡计算速率，总速率是各网卡增量之和。
+// 网卡上的本地地址由各平台单独读取，按网卡分组后用于双栈状态显示。
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+#[derive(Clone, Debug)]
+pub struct InterfaceSnapshot {
+    // 稳定标识：Windows 为 InterfaceLuid，Linux 为接口名
+    pub id: String,
+    pub name: String,
+    pub alias: String,
+    pub if_type: &'static str,
+    pub mac: String,
+    // bit/s，未知时为 0
+    pub link_speed: u64,
+    pub up: bool,
+    pub loopback: bool,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+impl InterfaceSnapshot {
+    // 与旧实现一致：只有在线且非回环的网卡计入总速率
+    pub fn counts_towards_total(&self) -> bool {
+        self.up && !self.loopback
+    }
+
+    pub fn matches(&self, entry: &str) -> bool {
+        matches_entry(entry, &self.id, &self.name, &self.alias)
+    }
+}
+
+// 配置中的网卡条目可以是 id、名称或别名，方便手工编辑配置文件
+fn matches_entry(entry: &str, id: &str, name: &str, alias: &str) -> bool {
+    entry == id || entry == name || entry == alias
+}
+
+#[derive(Serialize, Clone)]
+pub struct InterfaceStats {
+    id: String,
+    name: String,
+    alias: String,
+    if_type: String,
+    mac: String,
+    link_speed: u64,
+    up: bool,
+    #[serde(skip)]
+    loopback: bool,
+    // 是否计入顶部的下载/上传速度
+    counted: bool,
+    // KB/s，与 NetworkStats 一致
+    download_speed: f64,
+    upload_speed: f64,
+    rx_bytes: u64,
+    tx_bytes: u64,
+    // 本次间隔内的字节增量，用于流量用量统计
+    #[serde(skip)]
+    delta_rx_bytes: u64,
+    #[serde(skip)]
+    delta_tx_bytes: u64,
+}
+
+impl InterfaceStats {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn matches(&self, entry: &str) -> bool {
+        matches_entry(entry, &self.id, &self.name, &self.alias)
+    }
+
+    // 与 InterfaceSnapshot::counts_towards_total 相同，按网卡统计用量时排除回环和离线网卡
+    pub fn counts_towards_total(&self) -> bool {
+        self.up && !self.loopback
+    }
+
+    pub fn delta_bytes(&self) -> (u64, u64) {
+        (self.delta_rx_bytes, self.delta_tx_bytes)
+    }
+}
+
+pub fn format_mac(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+pub fn counters_by_id(snapshots: &[InterfaceSnapshot]) -> HashMap<String, (u64, u64)> {
+    snapshots
+        .iter()
+        .map(|s| (s.id.clone(), (s.rx_bytes, s.tx_bytes)))
+        .collect()
+}
+
+fn kb_per_sec(delta: u64, elapsed: f64) -> f64 {
+    if elapsed > 0.0 {
+        (delta as f64 / elapsed) / 1024.0
+    } else {
+        0.0
+    }
+}
+
+// 计数器回退有两种原因：
+// - 32 位计数器溢出（32 位 Linux 的 /proc/net/dev 等）：旧值接近 2^32、新值很小，按回绕计算
+// - 驱动重置、网卡重新插入等：没有可信的基准值，本次增量记为 0，下次从新值开始计算
+// 采集接口不告诉我们计数器的位数，64 位计数器从 3.xGiB 重置也符合回绕的特征，
+// 因此只在链路速率已知、且回绕后的增量不超过 elapsed 内链路能承载的流量时才按回绕计算；
+// 速率未知（tun、WireGuard、部分 Wi-Fi 及 PowerShell 回退）时一律按重置处理，最多少算一个间隔
+fn counter_delta(current: u64, last: u64, link_speed: u64, elapsed: f64) -> u64 {
+    if current >= last {
+        return current - last;
+    }
+
+    const U32_RANGE: u64 = 1 << 32;
+    if link_speed > 0 && (U32_RANGE / 4 * 3..U32_RANGE).contains(&last) && current < U32_RANGE / 4 {
+        let wrapped = U32_RANGE - last + current;
+        let max_bytes = link_speed as f64 / 8.0 * elapsed;
+        if wrapped as f64 <= max_bytes {
+            return wrapped;
+        }
+    }
+
+    0
+}
+
+pub struct Rates {
+    pub interfaces: Vec<InterfaceStats>,
+    // KB/s
+    pub download_speed: f64,
+    pub upload_speed: f64,
+    // 计入总速率的网卡在本次间隔内的字节增量之和，历史和用量按它记录，不再由速率反推
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+// 按网卡计算 elapsed 秒内的速率
+// 只有 counted 返回 true 的网卡计入总速率；上一次没有出现过的网卡没有基准值，本次速率记为 0，
+// 网卡消失时直接从总和中去掉，不会让其它网卡的增量出现跳变
+pub fn compute_rates(
+    snapshots: &[InterfaceSnapshot],
+    previous: &HashMap<String, (u64, u64)>,
+    elapsed: f64,
+    counted: impl Fn(&InterfaceSnapshot) -> bool,
+) -> Rates {
+    let mut stats = Vec::with_capacity(snapshots.len());
+    let mut total_received = 0u64;
+    let mut total_sent = 0u64;
+
+    for snapshot in snapshots {
+        let (delta_received, delta_sent) = match previous.get(&snapshot.id) {
+            Some(&(last_received, last_sent)) => (
+                counter_delta(snapshot.rx_bytes, last_received, snapshot.link_speed, elapsed),
+                counter_delta(snapshot.tx_bytes, last_sent, snapshot.link_speed, elapsed),
+            ),
+            None => (0, 0),
+        };
+
+        let counted = counted(snapshot);
+        if counted {
+            total_received += delta_received;
+            total_sent += delta_sent;
+        }
+
+        stats.push(InterfaceStats {
+            id: snapshot.id.clone(),
+            name: snapshot.name.clone(),
+            alias: snapshot.alias.clone(),
+            if_type: snapshot.if_type.to_string(),
+            mac: snapshot.mac.clone(),
+            link_speed: snapshot.link_speed,
+            up: snapshot.up,
+            loopback: snapshot.loopback,
+            counted,
+            download_speed: kb_per_sec(delta_received, elapsed),
+            upload_speed: kb_per_sec(delta_sent, elapsed),
+            rx_bytes: snapshot.rx_bytes,
+            tx_bytes: snapshot.tx_bytes,
+            delta_rx_bytes: delta_received,
+            delta_tx_bytes: delta_sent,
+        });
+    }
+
+    Rates {
+        interfaces: stats,
+        download_speed: kb_per_sec(total_received, elapsed),
+        upload_speed: kb_per_sec(total_sent, elapsed),
+        rx_bytes: total_received,
+        tx_bytes: total_sent,
+    }
+}
+
+// 网卡上配置的一个本地地址，interface_id 与 InterfaceSnapshot::id 一致
+pub struct LocalAddress {
+    pub interface_id: String,
+    pub addr: IpAddr,
+    pub prefix_len: u8,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AddressInfo {
+    pub address: String,
+    pub prefix_len: u8,
+    pub family: &'static str,
+    // global / private / link_local / loopback
+    pub scope: &'static str,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct InterfaceAddresses {
+    pub id: String,
+    pub name: String,
+    pub alias: String,
+    pub up: bool,
+    pub addresses: Vec<AddressInfo>,
+}
+
+// 私有地址包括 RFC 1918、CGNAT（100.64.0.0/10）和 IPv6 唯一本地地址（fc00::/7）
+pub fn address_scope(addr: &IpAddr) -> &'static str {
+    match addr {
+        IpAddr::V4(v4) if v4.is_loopback() => "loopback",
+        IpAddr::V4(v4) if v4.is_link_local() => "link_local",
+        IpAddr::V4(v4) if v4.is_private() || (v4.octets()[0] == 100 && v4.octets()[1] & 0xc0 == 64) => "private",
+        IpAddr::V6(v6) if v6.is_loopback() => "loopback",
+        IpAddr::V6(v6) if v6.is_unicast_link_local() => "link_local",
+        IpAddr::V6(v6) if v6.is_unique_local() => "private",
+        _ => "global",
+    }
+}
+
+// 按网卡分组，顺序与 snapshots 一致；没有地址的网卡不列出
+pub fn group_addresses(snapshots: &[InterfaceSnapshot], addresses: Vec<LocalAddress>) -> Vec<InterfaceAddresses> {
+    let mut by_id: HashMap<String, Vec<AddressInfo>> = HashMap::new();
+    for local in addresses {
+        by_id.entry(local.interface_id).or_default().push(AddressInfo {
+            address: local.addr.to_string(),
+            prefix_len: local.prefix_len,
+            family: if local.addr.is_ipv4() { "ipv4" } else { "ipv6" },
+            scope: address_scope(&local.addr),
+        });
+    }
+
+    let mut grouped: Vec<InterfaceAddresses> = snapshots
+        .iter()
+        .filter_map(|s| {
+            Some(InterfaceAddresses {
+                id: s.id.clone(),
+                name: s.name.clone(),
+                alias: s.alias.clone(),
+                up: s.up,
+                addresses: by_id.remove(&s.id)?,
+            })
+        })
+        .collect();
+
+    // 没有出现在流量统计中的网卡（如 PowerShell 回退时的隐藏网卡）按 id 列出
+    let mut rest: Vec<_> = by_id.into_iter().collect();
+    rest.sort_by(|a, b| a.0.cmp(&b.0));
+    grouped.extend(rest.into_iter().map(|(id, addresses)| InterfaceAddresses {
+        name: id.clone(),
+        alias: id.clone(),
+        id,
+        up: true,
+        addresses,
+    }));
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GBIT: u64 = 1_000_000_000;
+    const U32_RANGE: u64 = 1 << 32;
+
+    fn snapshot(id: &str, link_speed: u64, rx_bytes: u64, tx_bytes: u64) -> InterfaceSnapshot {
+        InterfaceSnapshot {
+            id: id.to_string(),
+            name: id.to_string(),
+            alias: id.to_string(),
+            if_type: "ethernet",
+            mac: String::new(),
+            link_speed,
+            up: true,
+            loopback: false,
+            rx_bytes,
+            tx_bytes,
+        }
+    }
+
+    #[test]
+    fn counter_delta_counts_normal_increase() {
+        assert_eq!(counter_delta(1500, 1000, 0, 1.0), 500);
+        assert_eq!(counter_delta(1000, 1000, GBIT, 1.0), 0);
+    }
+
+    #[test]
+    fn counter_delta_unwraps_32_bit_counter_within_link_capacity() {
+        // 1 Gbit/s 一秒最多 125 MB
+        let last = U32_RANGE - 1_000_000;
+        assert_eq!(counter_delta(2_000_000, last, GBIT, 1.0), 3_000_000);
+    }
+
+    #[test]
+    fn counter_delta_rejects_wrap_beyond_link_capacity() {
+        // 3.5 GiB 的计数器回到很小的值，需要 512 MiB 的增量，超过 1 Gbit/s 一秒的容量
+        let last = U32_RANGE / 8 * 7;
+        assert_eq!(counter_delta(1000, last, GBIT, 1.0), 0);
+    }
+
+    #[test]
+    fn counter_delta_treats_rollback_as_reset_when_link_speed_is_unknown() {
+        // 64 位计数器从 3.5 GiB 重置不能被当作回绕
+        let last = U32_RANGE / 8 * 7;
+        assert_eq!(counter_delta(1000, last, 0, 1.0), 0);
+        assert_eq!(counter_delta(10, U32_RANGE - 10, 0, 1.0), 0);
+    }
+
+    #[test]
+    fn counter_delta_treats_rollback_far_from_2_32_as_reset() {
+        assert_eq!(counter_delta(0, 5_000_000, GBIT, 1.0), 0);
+        assert_eq!(counter_delta(1000, 10 * U32_RANGE, GBIT, 1.0), 0);
+    }
+
+    #[test]
+    fn compute_rates_sums_counted_interfaces_only() {
+        let previous: HashMap<String, (u64, u64)> =
+            [("eth0".to_string(), (0, 0)), ("wg0".to_string(), (0, 0))].into_iter().collect();
+        let snapshots = [
+            snapshot("eth0", GBIT, 2048, 1024),
+            snapshot("wg0", 0, 4096, 4096),
+            snapshot("new0", GBIT, 8192, 8192),
+        ];
+
+        let rates = compute_rates(&snapshots, &previous, 2.0, |s| s.id != "wg0");
+        assert_eq!((rates.download_speed, rates.upload_speed), (1.0, 0.5));
+        assert_eq!((rates.rx_bytes, rates.tx_bytes), (2048, 1024));
+        // 上一次没有出现过的网卡没有基准值
+        assert_eq!(rates.interfaces[2].delta_bytes(), (0, 0));
+        assert_eq!(rates.interfaces[1].delta_bytes(), (4096, 4096));
+    }
+
+    #[test]
+    fn interface_stats_exclude_loopback_and_down_interfaces() {
+        let snapshots = [
+            snapshot("eth0", GBIT, 0, 0),
+            InterfaceSnapshot { loopback: true, ..snapshot("lo", 0, 0, 0) },
+            InterfaceSnapshot { up: false, ..snapshot("eth1", GBIT, 0, 0) },
+        ];
+
+        let rates = compute_rates(&snapshots, &HashMap::new(), 1.0, |_| true);
+        let counted: Vec<bool> = rates.interfaces.iter().map(InterfaceStats::counts_towards_total).collect();
+        assert_eq!(counted, [true, false, false]);
+    }
+}
@@ -0,0 +1,205 @@
+// 公网 IP 变化历史：IPv4 / IPv6 公网地址每次变化时记录一行，保存在 SQLite 中
+//
+// 用于发现 VPN 断开、运营商重新分配地址等情况。启动时读取每个协议最后记录的地址，
+// 应用未运行期间发生的变化在第一次检查时补记。记录保留一年。
+// 某个协议失去公网连接（例如断开 VPN 后没有 IPv6）时记录一行空地址。
+
+use crate::public_ip::{Family, IPInfo};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+pub const FILE_NAME: &str = "ip_history.sqlite3";
+pub const CHANGED_EVENT: &str = "ip-changed";
+
+const RETENTION_MS: u64 = 365 * 24 * 3600 * 1000;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct IpChange {
+    // Unix 毫秒
+    pub timestamp: u64,
+    pub family: Family,
+    // 失去该协议的公网连接时为空
+    pub ip: String,
+    // 上一次记录的地址，第一次记录时为 None
+    pub previous: Option<String>,
+    pub provider: String,
+    pub asn: Option<u32>,
+    pub as_org: String,
+    pub city: String,
+    pub country: String,
+}
+
+impl IpChange {
+    pub fn new(timestamp: u64, family: Family, info: IPInfo, previous: Option<String>) -> Self {
+        IpChange {
+            timestamp,
+            family,
+            ip: info.ip,
+            previous,
+            provider: info.provider,
+            asn: info.asn,
+            as_org: info.as_org,
+            city: info.city,
+            country: info.country,
+        }
+    }
+}
+
+impl IpChange {
+    pub fn lost(timestamp: u64, family: Family, previous: String) -> Self {
+        IpChange::new(timestamp, family, IPInfo::default(), Some(previous))
+    }
+}
+
+fn family_name(family: Family) -> &'static str {
+    match family {
+        Family::Ipv4 => "ipv4",
+        Family::Ipv6 => "ipv6",
+    }
+}
+
+pub struct IpHistory {
+    conn: Connection,
+}
+
+impl IpHistory {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("创建数据目录失败: {}", e))?;
+        }
+        let conn = Connection::open(path).map_err(|e| format!("打开 IP 历史数据库失败: {}", e))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS ip_changes (
+                ts INTEGER NOT NULL,
+                family TEXT NOT NULL,
+                ip TEXT NOT NULL,
+                previous TEXT,
+                provider TEXT NOT NULL,
+                asn INTEGER,
+                as_org TEXT NOT NULL,
+                city TEXT NOT NULL,
+                country TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS ip_changes_ts ON ip_changes (ts)",
+        )
+        .map_err(|e| format!("创建 IP 历史数据表失败: {}", e))?;
+        Ok(IpHistory { conn })
+    }
+
+    pub fn record(&self, change: &IpChange) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO ip_changes (ts, family, ip, previous, provider, asn, as_org, city, country)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    change.timestamp as i64,
+                    family_name(change.family),
+                    change.ip,
+                    change.previous,
+                    change.provider,
+                    change.asn,
+                    change.as_org,
+                    change.city,
+                    change.country,
+                ],
+            )
+            .map_err(|e| format!("写入 IP 历史失败: {}", e))?;
+
+        self.conn
+            .execute(
+                "DELETE FROM ip_changes WHERE ts < ?1",
+                params![change.timestamp.saturating_sub(RETENTION_MS) as i64],
+            )
+            .map_err(|e| format!("清理 IP 历史失败: {}", e))?;
+        Ok(())
+    }
+
+    // 该协议最后记录的地址，最后一次记录为失去连接时为空字符串
+    pub fn latest(&self, family: Family) -> Result<Option<String>, String> {
+        self.conn
+            .query_row(
+                "SELECT ip FROM ip_changes WHERE family = ?1 ORDER BY ts DESC, rowid DESC LIMIT 1",
+                params![family_name(family)],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("读取 IP 历史失败: {}", e))
+    }
+
+    // 最近的 limit 条记录，新的在前
+    pub fn recent(&self, limit: u32) -> Result<Vec<IpChange>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT ts, family, ip, previous, provider, asn, as_org, city, country
+                 FROM ip_changes ORDER BY ts DESC, rowid DESC LIMIT ?1",
+            )
+            .map_err(|e| format!("读取 IP 历史失败: {}", e))?;
+
+        let rows = stmt
+            .query_map(params![limit], |row| {
+                let family: String = row.get(1)?;
+                Ok(IpChange {
+                    timestamp: row.get::<_, i64>(0)? as u64,
+                    family: if family == "ipv6" { Family::Ipv6 } else { Family::Ipv4 },
+                    ip: row.get(2)?,
+                    previous: row.get(3)?,
+                    provider: row.get(4)?,
+                    asn: row.get(5)?,
+                    as_org: row.get(6)?,
+                    city: row.get(7)?,
+                    country: row.get(8)?,
+                })
+            })
+            .map_err(|e| format!("读取 IP 历史失败: {}", e))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("读取 IP 历史失败: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(timestamp: u64, family: Family, ip: &str, previous: Option<&str>) -> IpChange {
+        let info = IPInfo { ip: ip.to_string(), provider: "ipify".to_string(), ..Default::default() };
+        IpChange::new(timestamp, family, info, previous.map(str::to_string))
+    }
+
+    #[test]
+    fn records_changes_and_losses_per_family() {
+        let history = IpHistory::open(Path::new(":memory:")).unwrap();
+        assert_eq!(history.latest(Family::Ipv6), Ok(None));
+
+        history.record(&change(1_000, Family::Ipv4, "203.0.113.7", None)).unwrap();
+        history.record(&change(2_000, Family::Ipv6, "2001:db8::1", None)).unwrap();
+        history.record(&IpChange::lost(3_000, Family::Ipv6, "2001:db8::1".to_string())).unwrap();
+
+        assert_eq!(history.latest(Family::Ipv4), Ok(Some("203.0.113.7".to_string())));
+        assert_eq!(history.latest(Family::Ipv6), Ok(Some(String::new())));
+
+        let recent = history.recent(10).unwrap();
+        let rows: Vec<(u64, &str, Option<&str>)> =
+            recent.iter().map(|c| (c.timestamp, c.ip.as_str(), c.previous.as_deref())).collect();
+        assert_eq!(
+            rows,
+            [(3_000, "", Some("2001:db8::1")), (2_000, "2001:db8::1", None), (1_000, "203.0.113.7", None)]
+        );
+        assert_eq!(recent[0].family, Family::Ipv6);
+        assert_eq!(recent[0].provider, "");
+    }
+
+    #[test]
+    fn prunes_records_older_than_a_year() {
+        let history = IpHistory::open(Path::new(":memory:")).unwrap();
+        history.record(&change(1_000, Family::Ipv4, "203.0.113.7", None)).unwrap();
+        history.record(&change(1_000 + RETENTION_MS + 1, Family::Ipv4, "203.0.113.8", Some("203.0.113.7"))).unwrap();
+
+        let recent = history.recent(10).unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].ip, "203.0.113.8");
+    }
+}
@@ -1,116 +1,112 @@
 use serde::Serialize;
-use std::fs::{OpenOptions, File};
-use std::io::{Write, BufRead, BufReader};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_notification::NotificationExt;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::settings::ConfigFile;
+use tracing::{debug, error, info, trace, warn};
 
 #[cfg(target_os = "windows")]
 use windows::Win32::Foundation::ERROR_SUCCESS;
 
-const LOG_PATH: &str = "D:\\code\\network-stats.log";
-const MAX_HEAD_LINES: usize = 200;
-const MAX_TAIL_LINES: usize = 200;
-
-// 日志滚动：当文件超过限制时，保留头部和尾部
-fn rotate_log_if_needed() {
-    let file = match File::open(LOG_PATH) {
-        Ok(f) => f,
-        Err(_) => return,
-    };
-
-    let reader = BufReader::new(file);
-    let lines: Vec<String> = reader.lines().filter_map(|l| l.ok()).collect();
-
-    // 如果行数超过限制，进行滚动
-    if lines.len() > MAX_HEAD_LINES + MAX_TAIL_LINES {
-        let head: Vec<&String> = lines.iter().take(MAX_HEAD_LINES).collect();
-        let tail: Vec<&String> = lines.iter().skip(lines.len() - MAX_TAIL_LINES).collect();
-
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-
-        let mut output = match File::create(LOG_PATH) {
-            Ok(f) => f,
-            Err(_) => return,
-        };
-
-        // 写入头部
-        for line in &head {
-            let _ = writeln!(output, "{}", line);
-        }
-
-        // 写入分隔符
-        let _ = writeln!(output, "");
-        let _ = writeln!(output, "--- === 日志滚动于 {}，已省略 {} 行 === ---",
-            now, lines.len() - MAX_HEAD_LINES - MAX_TAIL_LINES);
-        let _ = writeln!(output, "");
-
-        // 写入尾部
-        for line in &tail {
-            let _ = writeln!(output, "{}", line);
-        }
-    }
-}
-
-macro_rules! log_msg {
-    ($($arg:tt)*) => {
-        {
-            let msg = format!($($arg)*);
-            if let Ok(mut file) = OpenOptions::new()
-                .create(true)
-                .append(true)
-                .write(true)
-                .open(LOG_PATH) {
-                let _ = writeln!(file, "{}", msg);
-            }
-            // 检查是否需要滚动日志（每100条日志检查一次，避免频繁IO）
-            // 使用静态计数器
-            static mut LOG_COUNTER: u32 = 0;
-            unsafe {
-                LOG_COUNTER += 1;
-                if LOG_COUNTER % 100 == 0 {
-                    rotate_log_if_needed();
-                }
-            }
-        }
-    };
-}
+#[cfg(target_os = "linux")]
+mod linux_net;
+mod alerts;
+mod geoip;
+mod history;
+mod interface_filter;
+mod interfaces;
+mod ip_history;
+mod logging;
+mod ping;
+mod probe;
+mod public_ip;
+mod quality;
+mod quota;
+mod settings;
+mod usage;
 
 #[derive(Serialize, Clone, Default)]
 struct NetworkStats {
+    // 每次后台计算加 1，前端据此识别重复或遗漏的样本
+    seq: u64,
+    // 计算时间，Unix 毫秒
+    timestamp: u64,
     latency: u32,
     download_speed: f64,
     upload_speed: f64,
     packet_loss: f64,
-    status: String,
+    // 稳定标识，前端据此选择样式和文字
+    quality: quality::ConnectionQuality,
+    // 0-100 分
+    quality_score: f64,
+    // ITU-T G.107 E-model 估算的语音通话 R 因子（0-100）和 MOS（1-4.5）
+    r_factor: f64,
+    mos: f64,
+    // 以下均为毫秒，统计最近一分钟的探测结果
+    jitter: f64,
+    latency_min: f64,
+    latency_max: f64,
+    latency_p50: f64,
+    latency_p95: f64,
 }
 
 #[derive(Default)]
 struct NetworkState {
-    last_bytes_received: u64,
-    last_bytes_sent: u64,
+    // 按网卡 id 记录上一次的累计 (接收, 发送) 字节数
+    last_counters: HashMap<String, (u64, u64)>,
     last_bytes_update: Option<Instant>,
     current_stats: NetworkStats,
-    cached_received: u64,
-    cached_sent: u64,
-    last_latency_update: Option<Instant>,
+    interface_stats: Vec<interfaces::InterfaceStats>,
+    interface_filter: interface_filter::InterfaceFilter,
+    interface_filter_path: Option<PathBuf>,
+    // 承载默认路由的网卡 id，由探测线程查找网关时更新
+    default_route_interface: Option<String>,
+    probe: probe::ProbeSession,
+    // 订阅了 network-stats 事件的窗口 label
+    subscribers: HashSet<String>,
+    // 打开失败时为 None，不影响实时统计
+    // 单独加锁：后台线程释放状态锁后再写数据库，写入慢时不阻塞命令和事件推送
+    history: Option<Arc<Mutex<history::HistoryStore>>>,
+    usage: Option<Arc<Mutex<usage::UsageLedger>>>,
+    ip_history: Option<ip_history::IpHistory>,
+    usage_config: usage::UsageConfig,
+    usage_config_path: Option<PathBuf>,
+    quota_config: quota::QuotaConfig,
+    quota_config_path: Option<PathBuf>,
+    alert_config: alerts::AlertConfig,
+    alert_config_path: Option<PathBuf>,
+    alert_engine: alerts::AlertEngine,
+    quality_config: quality::QualityConfig,
+    quality_config_path: Option<PathBuf>,
+    settings_path: Option<PathBuf>,
+    // 保持监听配置目录，drop 后停止监听
+    config_watcher: Option<notify::RecommendedWatcher>,
 }
 
+// 后台线程每计算一次统计就向订阅的窗口推送一次
+const STATS_EVENT: &str = "network-stats";
+
+// 每 10 个样本检查一次配额
+const QUOTA_CHECK_SAMPLES: u64 = 10;
+
 static mut BG_THREAD_HANDLE: Option<thread::JoinHandle<()>> = None;
 
-// IP and Weather structures
+// 公网双栈状态和各网卡的本地地址
 #[derive(Serialize, Clone)]
-struct IPInfo {
-    ip: String,
-    city: String,
-    country: String,
-    timezone: String,  // 新增：IP所在地的时区
+struct DualStackInfo {
+    ipv4: public_ip::FamilyStatus,
+    ipv6: public_ip::FamilyStatus,
+    interfaces: Vec<interfaces::InterfaceAddresses>,
 }
 
+// Weather structures
 #[derive(Serialize, Clone)]
 struct WeatherInfo {
     temp: String,
@@ -121,107 +117,37 @@ struct WeatherInfo {
     icon: String,
 }
 
-// Helper: extract IP from text
-fn extract_ip(text: &str) -> Option<String> {
-    let _trimmed = text.trim();
-
-    // Use regex-like pattern to find IP: xxx.xxx.xxx.xxx
-    // Look for pattern where we have digits.digits.digits.digits
-    let chars: Vec<char> = text.chars().collect();
-    let mut i = 0;
-
-    while i < chars.len() {
-        // Try to find an IP starting at position i
-        let mut octet_start = i;
-        let mut octets = Vec::new();
-        let mut valid = true;
-
-        for octet_num in 0..4 {
-            // Find start of octet (digit)
-            while octet_start < chars.len() && !chars[octet_start].is_ascii_digit() {
-                octet_start += 1;
-            }
-
-            if octet_start >= chars.len() {
-                valid = false;
-                break;
-            }
-
-            // Find end of octet
-            let mut octet_end = octet_start;
-            while octet_end < chars.len() && chars[octet_end].is_ascii_digit() {
-                octet_end += 1;
-            }
-
-            let octet_str: String = chars[octet_start..octet_end].iter().collect();
-            let octet_val: u32 = octet_str.parse().unwrap_or(256);
-
-            if octet_val > 255 {
-                valid = false;
-                break;
-            }
-
-            octets.push(octet_str);
-
-            // Check for dot between octets (except after last octet)
-            if octet_num < 3 {
-                if octet_end >= chars.len() || chars[octet_end] != '.' {
-                    valid = false;
-                    break;
-                }
-                octet_start = octet_end + 1;
-            } else {
-                // After 4th octet, should not be followed by digit or dot
-                if octet_end < chars.len() && (chars[octet_end].is_ascii_digit() || chars[octet_end] == '.') {
-                    // Check if next char could extend the IP (more digits or octets)
-                    if octet_end < chars.len() && chars[octet_end].is_ascii_digit() {
-                        valid = false;
-                    }
-                }
-            }
-        }
-
-        if valid && octets.len() == 4 {
-            return Some(format!("{}.{}.{}.{}", octets[0], octets[1], octets[2], octets[3]));
-        }
+#[cfg(target_os = "windows")]
+fn if_type_name(if_type: u32) -> &'static str {
+    use windows::Win32::NetworkManagement::IpHelper::{
+        IF_TYPE_ETHERNET_CSMACD, IF_TYPE_IEEE80211, IF_TYPE_PPP, IF_TYPE_PROP_VIRTUAL,
+        IF_TYPE_SOFTWARE_LOOPBACK, IF_TYPE_TUNNEL, IF_TYPE_WWANPP, IF_TYPE_WWANPP2,
+    };
 
-        i += 1;
+    match if_type {
+        IF_TYPE_ETHERNET_CSMACD => "ethernet",
+        IF_TYPE_IEEE80211 => "wifi",
+        IF_TYPE_SOFTWARE_LOOPBACK => "loopback",
+        IF_TYPE_TUNNEL => "tunnel",
+        IF_TYPE_PPP => "ppp",
+        IF_TYPE_WWANPP | IF_TYPE_WWANPP2 => "mobile",
+        IF_TYPE_PROP_VIRTUAL => "virtual",
+        _ => "other",
     }
-
-    None
 }
 
-// Helper: extract city from Chinese response
-fn extract_city(text: &str) -> String {
-    let cities = [
-        ("上海", "Shanghai"),
-        ("北京", "Beijing"),
-        ("广州", "Guangzhou"),
-        ("深圳", "Shenzhen"),
-        ("天津", "Tianjin"),
-        ("杭州", "Hangzhou"),
-        ("成都", "Chengdu"),
-        ("重庆", "Chongqing"),
-        ("武汉", "Wuhan"),
-        ("西安", "Xian"),
-    ];
-
-    for (chinese, english) in cities.iter() {
-        if text.contains(chinese) {
-            return english.to_string();
-        }
-    }
-
-    "本地".to_string()
-}
-
-// Get network bytes using Windows API
+// Get per-interface counters using Windows API
 #[cfg(target_os = "windows")]
-fn get_network_bytes_api() -> Option<(u64, u64)> {
+fn collect_interfaces_api() -> Option<Vec<interfaces::InterfaceSnapshot>> {
     use windows::Win32::NetworkManagement::IpHelper::{
-        GetIfTable2, FreeMibTable, MIB_IF_TABLE2,
+        GetIfTable2, FreeMibTable, MIB_IF_TABLE2, IF_TYPE_SOFTWARE_LOOPBACK,
     };
 
+    fn wide_to_string(wide: &[u16]) -> String {
+        let len = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+        String::from_utf16_lossy(&wide[..len])
+    }
+
     unsafe {
         let mut if_table_ptr: *mut MIB_IF_TABLE2 = std::ptr::null_mut();
         let ret = GetIfTable2(&mut if_table_ptr);
@@ -231,71 +157,96 @@ fn get_network_bytes_api() -> Option<(u64, u64)> {
         }
 
         let table = &*if_table_ptr;
-        let mut total_received = 0u64;
-        let mut total_sent = 0u64;
-        let mut active_count = 0u32;
+        let mut snapshots = Vec::new();
 
         const IF_OPER_STATUS_OPERATIONAL: i32 = 1;
-        const IF_TYPE_SOFTWARE_LOOPBACK: u32 = 24;
+        // InterfaceAndOperStatusFlags 的第 2 位：NDIS 过滤驱动（如 WFP、QoS）挂在真实网卡上的
+        // 虚拟接口，计数与底层网卡相同，计入会重复统计
+        const FILTER_INTERFACE_FLAG: u8 = 0x2;
 
         let rows_ptr = table.Table.as_ptr();
         for i in 0..table.NumEntries as isize {
             let row = &*rows_ptr.offset(i);
 
-            if row.OperStatus.0 != IF_OPER_STATUS_OPERATIONAL {
-                continue;
-            }
-
-            let if_type = row.Type;
-            if if_type == IF_TYPE_SOFTWARE_LOOPBACK {
+            if row.InterfaceAndOperStatusFlags._bitfield & FILTER_INTERFACE_FLAG != 0 {
                 continue;
             }
 
-            total_received += row.InOctets;
-            total_sent += row.OutOctets;
-            active_count += 1;
+            let mac_len = (row.PhysicalAddressLength as usize).min(row.PhysicalAddress.len());
+
+            snapshots.push(interfaces::InterfaceSnapshot {
+                id: format!("{:016x}", row.InterfaceLuid.Value),
+                name: wide_to_string(&row.Description),
+                alias: wide_to_string(&row.Alias),
+                if_type: if_type_name(row.Type),
+                mac: interfaces::format_mac(&row.PhysicalAddress[..mac_len]),
+                link_speed: row.ReceiveLinkSpeed.max(row.TransmitLinkSpeed),
+                up: row.OperStatus.0 == IF_OPER_STATUS_OPERATIONAL,
+                loopback: row.Type == IF_TYPE_SOFTWARE_LOOPBACK,
+                rx_bytes: row.InOctets,
+                tx_bytes: row.OutOctets,
+            });
         }
 
         FreeMibTable(if_table_ptr as _);
 
+        let active_count = snapshots.iter().filter(|s| s.counts_towards_total()).count();
         if active_count == 0 {
             None
         } else {
-            log_msg!("API: {} active interfaces", active_count);
-            Some((total_received, total_sent))
+            trace!(active_count, "API interfaces");
+            Some(snapshots)
         }
     }
 }
 
-// PowerShell fallback for network bytes
+// PowerShell fallback for per-interface counters
 #[cfg(target_os = "windows")]
-fn get_network_bytes_ps() -> Option<(u64, u64)> {
+fn collect_interfaces_ps() -> Option<Vec<interfaces::InterfaceSnapshot>> {
+    // 用制表符分隔，网卡名中可能含有逗号
     let script = r#"
         Get-NetAdapter | Where-Object { $_.Status -eq 'Up' } | ForEach-Object {
             $stats = Get-NetAdapterStatistics -Name $_.Name -ErrorAction SilentlyContinue
             if ($stats) {
-                Write-Output "$($stats.ReceivedBytes),$($stats.SentBytes)"
+                Write-Output "$($_.ifIndex)`t$($_.InterfaceDescription)`t$($_.Name)`t$($_.MacAddress)`t$($stats.ReceivedBytes)`t$($stats.SentBytes)"
             }
         }
     "#;
 
-    let output = std::process::Command::new("powershell")
+    let output = hidden_command("powershell")
         .args(["-WindowStyle", "Hidden", "-NoProfile", "-NonInteractive", "-Command", script])
-        .creation_flags(0x08000000)
         .output();
 
     match output {
         Ok(result) => {
             let stdout = String::from_utf8_lossy(&result.stdout);
-            let trimmed = stdout.trim();
+            let snapshots: Vec<interfaces::InterfaceSnapshot> = stdout
+                .lines()
+                .filter_map(|line| {
+                    let fields: Vec<&str> = line.trim().split('\t').collect();
+                    if fields.len() < 6 {
+                        return None;
+                    }
+                    Some(interfaces::InterfaceSnapshot {
+                        id: format!("ps:{}", fields[0].trim()),
+                        name: fields[1].trim().to_string(),
+                        alias: fields[2].trim().to_string(),
+                        if_type: "other",
+                        mac: fields[3].trim().replace('-', ":"),
+                        link_speed: 0,
+                        up: true,
+                        loopback: false,
+                        rx_bytes: fields[4].trim().parse::<u64>().unwrap_or(0),
+                        tx_bytes: fields[5].trim().parse::<u64>().unwrap_or(0),
+                    })
+                })
+                .collect();
 
-            if let Some(pos) = trimmed.find(',') {
-                let received = trimmed[..pos].trim().parse::<u64>().unwrap_or(0);
-                let sent = trimmed[pos + 1..].trim().parse::<u64>().unwrap_or(0);
-                log_msg!("PS: recv={}, sent={}", received, sent);
-                Some((received, sent))
-            } else {
+            if snapshots.is_empty() {
                 None
+            } else {
+                trace!(count = snapshots.len(), "PowerShell interfaces");
+                Some(snapshots)
             }
         }
         Err(_) => None,
@@ -303,222 +254,627 @@ fn get_network_bytes_ps() -> Option<(u64, u64)> {
 }
 
 #[cfg(target_os = "windows")]
-fn get_network_bytes() -> Option<(u64, u64)> {
-    get_network_bytes_api().or_else(get_network_bytes_ps)
+fn collect_interfaces() -> Option<Vec<interfaces::InterfaceSnapshot>> {
+    collect_interfaces_api().or_else(collect_interfaces_ps)
 }
 
-#[cfg(not(target_os = "windows"))]
-fn get_network_bytes() -> Option<(u64, u64)> {
-    Some((0, 0))
+#[cfg(target_os = "linux")]
+fn collect_interfaces() -> Option<Vec<interfaces::InterfaceSnapshot>> {
+    linux_net::read_interfaces(std::path::Path::new("/"))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn collect_interfaces() -> Option<Vec<interfaces::InterfaceSnapshot>> {
+    None
 }
 
-// Ping gateway
+// 各网卡的单播地址（IPv4 和 IPv6）
 #[cfg(target_os = "windows")]
-fn ping_gateway_internal() -> (u32, f64) {
-    use std::process::Command;
-
-    log_msg!("Pinging gateway...");
-
-    let gateway_output = Command::new("powershell")
-        .args([
-            "-WindowStyle", "Hidden",
-            "-NoProfile",
-            "-NonInteractive",
-            "-Command",
-            "(Get-NetRoute -DestinationPrefix '0.0.0.0/0' | Select-Object -First 1).NextHop",
-        ])
-        .creation_flags(0x08000000)
-        .output();
+fn collect_local_addresses() -> Vec<interfaces::LocalAddress> {
+    use windows::Win32::NetworkManagement::IpHelper::{
+        GetUnicastIpAddressTable, FreeMibTable, MIB_UNICASTIPADDRESS_TABLE,
+    };
+    use windows::Win32::Networking::WinSock::{AF_INET, AF_INET6, AF_UNSPEC};
 
-    let target_ip = if let Ok(result) = gateway_output {
-        let ip = String::from_utf8_lossy(&result.stdout).trim().to_string();
-        if !ip.is_empty() && ip.contains('.') {
-            log_msg!("Gateway: {}", ip);
-            ip
-        } else {
-            log_msg!("No valid gateway, using 8.8.8.8");
-            "8.8.8.8".to_string()
+    let mut addresses = Vec::new();
+
+    unsafe {
+        let mut table_ptr: *mut MIB_UNICASTIPADDRESS_TABLE = std::ptr::null_mut();
+        let ret = GetUnicastIpAddressTable(AF_UNSPEC, &mut table_ptr);
+
+        if ret != ERROR_SUCCESS || table_ptr.is_null() {
+            return addresses;
         }
-    } else {
-        log_msg!("Failed to get gateway, using 8.8.8.8");
-        "8.8.8.8".to_string()
+
+        let table = &*table_ptr;
+        let rows_ptr = table.Table.as_ptr();
+        for i in 0..table.NumEntries as isize {
+            let row = &*rows_ptr.offset(i);
+
+            let addr = if row.Address.si_family == AF_INET {
+                IpAddr::V4(Ipv4Addr::from(row.Address.Ipv4.sin_addr.S_un.S_addr.to_ne_bytes()))
+            } else if row.Address.si_family == AF_INET6 {
+                IpAddr::V6(std::net::Ipv6Addr::from(row.Address.Ipv6.sin6_addr.u.Byte))
+            } else {
+                continue;
+            };
+
+            addresses.push(interfaces::LocalAddress {
+                interface_id: format!("{:016x}", row.InterfaceLuid.Value),
+                addr,
+                prefix_len: row.OnLinkPrefixLength,
+            });
+        }
+
+        FreeMibTable(table_ptr as _);
+    }
+
+    addresses
+}
+
+#[cfg(target_os = "linux")]
+fn collect_local_addresses() -> Vec<interfaces::LocalAddress> {
+    linux_net::local_addresses()
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn collect_local_addresses() -> Vec<interfaces::LocalAddress> {
+    Vec::new()
+}
+
+// 创建不弹出控制台窗口的进程（CREATE_NO_WINDOW）
+#[cfg(target_os = "windows")]
+fn hidden_command(program: &str) -> std::process::Command {
+    let mut cmd = std::process::Command::new(program);
+    cmd.creation_flags(0x08000000);
+    cmd
+}
+
+// 默认路由：下一跳、IPv6 scope id、出口网卡 id（与 InterfaceSnapshot::id 一致）
+struct DefaultRoute {
+    gateway: IpAddr,
+    scope_id: u32,
+    interface_id: String,
+}
+
+// 从 IPv4 路由表中找 metric 最小的 0.0.0.0/0 路由
+#[cfg(target_os = "windows")]
+fn find_default_route() -> Option<DefaultRoute> {
+    use windows::Win32::NetworkManagement::IpHelper::{
+        GetIpForwardTable2, FreeMibTable, MIB_IPFORWARD_TABLE2,
     };
+    use windows::Win32::Networking::WinSock::AF_INET;
 
-    let output = Command::new("ping")
-        .args(["-n", "1", "-w", "2000", &target_ip])
-        .creation_flags(0x08000000)
-        .output();
+    unsafe {
+        let mut table_ptr: *mut MIB_IPFORWARD_TABLE2 = std::ptr::null_mut();
+        let ret = GetIpForwardTable2(AF_INET, &mut table_ptr);
 
-    match output {
-        Ok(result) => {
-            let stdout = String::from_utf8_lossy(&result.stdout);
-            log_msg!("Ping output: {}", stdout.trim());
-
-            // Check for packet loss
-            if stdout.contains("100% loss") || stdout.contains("timed out") ||
-               stdout.contains("unreachable") || stdout.contains("General failure") {
-                log_msg!("Ping: packet loss detected");
-                return (0, 100.0);
-            }
-
-            // Parse latency
-            for line in stdout.lines() {
-                if line.contains("ms") || line.contains("MS") {
-                    let ms_pos = line.find("ms").or_else(|| line.find("MS")).unwrap_or(0);
-                    if ms_pos > 2 {
-                        let before_ms = &line[..ms_pos];
-                        if let Some(last_space) = before_ms.rfind(' ') {
-                            let num_str = &before_ms[last_space + 1..];
-                            if let Ok(latency) = num_str.trim().parse::<f64>() {
-                                log_msg!("Latency: {}ms", latency);
-                                return (latency as u32, 0.0);
-                            }
-                        } else if let Some(last_eq) = before_ms.rfind('=') {
-                            let num_str = &before_ms[last_eq + 1..];
-                            if let Ok(latency) = num_str.trim().parse::<f64>() {
-                                log_msg!("Latency: {}ms", latency);
-                                return (latency as u32, 0.0);
-                            }
-                        }
-                    }
+        if ret != ERROR_SUCCESS || table_ptr.is_null() {
+            return None;
+        }
+
+        let table = &*table_ptr;
+        let mut best: Option<(u32, DefaultRoute)> = None;
+
+        let rows_ptr = table.Table.as_ptr();
+        for i in 0..table.NumEntries as isize {
+            let row = &*rows_ptr.offset(i);
+
+            if row.DestinationPrefix.PrefixLength != 0 {
+                continue;
+            }
+
+            let next_hop = Ipv4Addr::from(row.NextHop.Ipv4.sin_addr.S_un.S_addr.to_ne_bytes());
+            if next_hop.is_unspecified() {
+                continue;
+            }
+
+            if best.as_ref().is_none_or(|(metric, _)| row.Metric < *metric) {
+                best = Some((row.Metric, DefaultRoute {
+                    gateway: IpAddr::V4(next_hop),
+                    scope_id: 0,
+                    interface_id: format!("{:016x}", row.InterfaceLuid.Value),
+                }));
+            }
+        }
+
+        FreeMibTable(table_ptr as _);
+
+        best.map(|(_, route)| route)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn find_default_route() -> Option<DefaultRoute> {
+    let gateway = linux_net::default_gateway(std::path::Path::new("/"))?;
+    Some(DefaultRoute {
+        gateway: gateway.addr,
+        scope_id: gateway.ifindex,
+        interface_id: gateway.interface,
+    })
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn find_default_route() -> Option<DefaultRoute> {
+    None
+}
+
+// 确定探测目标：默认网关，找不到时使用 8.8.8.8
+fn resolve_probe_target() -> (IpAddr, u32, Option<String>) {
+    match find_default_route() {
+        Some(route) => {
+            info!("Gateway: {} via {}", route.gateway, route.interface_id);
+            (route.gateway, route.scope_id, Some(route.interface_id))
+        }
+        None => {
+            warn!("No valid gateway, using 8.8.8.8");
+            (IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)), 0, None)
+        }
+    }
+}
+
+// 网关每分钟重新查找一次；连续丢包时立即重新查找（可能切换了网络）
+const GATEWAY_REFRESH: Duration = Duration::from_secs(60);
+const GATEWAY_RELOOKUP_LOSSES: u32 = 3;
+
+// 延迟探测线程：按设置的间隔向网关发送 echo，结果写入滑动窗口
+fn latency_prober(state: Arc<Mutex<NetworkState>>) {
+    info!("Latency prober thread started");
+
+    let mut target: Option<(IpAddr, u32)> = None;
+    let mut last_lookup = Instant::now();
+    let mut consecutive_losses = 0u32;
+
+    loop {
+        let started = Instant::now();
+        let sampling = settings::with(|s| s.sampling.clone());
+
+        let relookup = target.is_none()
+            || last_lookup.elapsed() >= GATEWAY_REFRESH
+            || consecutive_losses >= GATEWAY_RELOOKUP_LOSSES;
+        if relookup {
+            let (gateway, scope_id, interface_id) = resolve_probe_target();
+            let new_target = (gateway, scope_id);
+            if let Ok(mut state_guard) = state.lock() {
+                if target.is_some_and(|old| old != new_target) {
+                    state_guard.probe.clear();
                 }
+                state_guard.default_route_interface = interface_id;
             }
+            target = Some(new_target);
+            last_lookup = Instant::now();
+            consecutive_losses = 0;
+        }
 
-            // If bytes and TTL present but no time, it's <1ms
-            if stdout.contains("bytes=") && stdout.contains("TTL=") {
-                log_msg!("Latency: <1ms");
-                return (1, 0.0);
+        let (target_ip, scope_id) = target.unwrap_or((IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)), 0));
+        let result = ping::echo(target_ip, scope_id, sampling.probe_timeout());
+
+        match &result {
+            Ok(reply) => {
+                if relookup || consecutive_losses > 0 {
+                    debug!(gateway = %target_ip, rtt_us = reply.rtt_us, ttl = ?reply.ttl, "ping");
+                }
+                consecutive_losses = 0;
             }
+            Err(e) => {
+                consecutive_losses += 1;
+                warn!(gateway = %target_ip, "ping failed: {}", e);
+            }
+        }
 
-            if stdout.contains("TTL=") {
-                log_msg!("Ping succeeded but no time, using default");
-                return (5, 0.0);
+        match state.lock() {
+            Ok(mut state_guard) => {
+                state_guard.probe.set_window(sampling.probe_window);
+                state_guard.probe.record(&result);
             }
+            Err(_) => error!("Failed to lock state in prober thread"),
+        }
 
-            log_msg!("Ping parsing failed");
-            (0, 100.0)
+        thread::sleep(sampling.probe_interval().saturating_sub(started.elapsed()));
+    }
+}
+
+// 公网 IP 变化检测：每 5 秒检查一次是否到期或默认路由网卡变化（如连接 / 断开 VPN）
+const IP_WATCH_POLL: Duration = Duration::from_secs(5);
+// 两个协议都查询失败后从 30 秒开始退避，每次翻倍，最长 30 分钟
+const IP_WATCH_RETRY: Duration = Duration::from_secs(30);
+const IP_WATCH_MAX_RETRY: Duration = Duration::from_secs(30 * 60);
+
+// 分别查询 IPv4 和 IPv6 公网地址，与上次记录不同时写入历史并发送 ip-changed 事件
+// 之前有地址的协议没有任何接口能连上时（例如断开 VPN 后没有 IPv6）记为失去地址，last 中记为空字符串；
+// 有接口响应但没有得到地址（限流、HTTP 错误）时保留上次的地址，不记为变化。
+// 两个协议都失败时返回 Err，此时多半是本机离线，不记录失去地址
+async fn check_public_ip(
+    state: &Arc<Mutex<NetworkState>>,
+    app: &AppHandle,
+    last: &mut HashMap<public_ip::Family, String>,
+) -> Result<(), String> {
+    use public_ip::Family;
+
+    let (mut providers, timeout, databases) = settings::with(|s| {
+        (s.public_ip.providers.clone(), s.public_ip.timeout(), s.public_ip.geo_databases.clone())
+    });
+    // 变化检测只需要地址：优先使用只返回 IP 的接口，不占用地理位置接口的额度，地理位置由离线数据库补齐
+    providers.sort_by_key(|provider| !provider.ip_only());
+    let health = app.state::<public_ip::ProviderHealth>();
+    let ipv4_client = public_ip::client(timeout, Some(Family::Ipv4))?;
+    let ipv6_client = public_ip::client(timeout, Some(Family::Ipv6))?;
+
+    let ((ipv4, ipv4_connected), (ipv6, ipv6_connected)) = tokio::join!(
+        public_ip::try_providers(&ipv4_client, &providers, &health, Some(Family::Ipv4)),
+        public_ip::try_providers(&ipv6_client, &providers, &health, Some(Family::Ipv6)),
+    );
+    if ipv4.is_err() && ipv6.is_err() {
+        return Err("IPv4 和 IPv6 公网地址都查询失败".to_string());
+    }
+
+    for (family, result, connected) in [(Family::Ipv4, ipv4, ipv4_connected), (Family::Ipv6, ipv6, ipv6_connected)] {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        let change = match result {
+            Ok(info) => {
+                if last.get(&family) == Some(&info.ip) {
+                    continue;
+                }
+                // 只在变化时查询离线数据库，补齐 ASN 等信息；查询失败时按接口返回的信息记录
+                let info = match fill_geo(info.clone(), databases.clone()).await {
+                    Ok(filled) => filled,
+                    Err(e) => {
+                        warn!("Geo lookup for {} failed: {}", info.ip, e);
+                        info
+                    }
+                };
+                let previous = last.get(&family).filter(|ip| !ip.is_empty()).cloned();
+                ip_history::IpChange::new(timestamp, family, info, previous)
+            }
+            Err(_) if connected => continue,
+            Err(_) => {
+                let Some(previous) = last.get(&family).filter(|ip| !ip.is_empty()).cloned() else {
+                    continue;
+                };
+                ip_history::IpChange::lost(timestamp, family, previous)
+            }
+        };
+
+        // 写入历史成功后才更新 last；写入失败时保留旧地址，下次检查时重新记录
+        let recorded = match state.lock() {
+            Ok(state_guard) => match state_guard.ip_history.as_ref() {
+                Some(store) => match store.record(&change) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        warn!("Failed to record IP change, retrying on next check: {}", e);
+                        false
+                    }
+                },
+                // 历史数据库不可用时只在内存中比较
+                None => true,
+            },
+            Err(_) => false,
+        };
+        if !recorded {
+            continue;
         }
-        Err(e) => {
-            log_msg!("Ping error: {}", e);
-            (0, 100.0)
+        last.insert(family, change.ip.clone());
+
+        info!(
+            "Public {:?} changed: {} -> {} (provider: {}, ASN: {:?})",
+            family,
+            change.previous.as_deref().unwrap_or("none"),
+            if change.ip.is_empty() { "none" } else { change.ip.as_str() },
+            change.provider,
+            change.asn
+        );
+        if let Err(e) = app.emit(ip_history::CHANGED_EVENT, &change) {
+            warn!("Failed to emit IP change: {}", e);
         }
     }
+
+    Ok(())
 }
 
-#[cfg(not(target_os = "windows"))]
-fn ping_gateway_internal() -> (u32, f64) {
-    (30, 0.0)
+async fn ip_watcher(state: Arc<Mutex<NetworkState>>, app: AppHandle) {
+    use public_ip::Family;
+
+    info!("Public IP watcher started");
+
+    // 从历史中恢复上次的地址，应用未运行期间的变化在第一次检查时记录
+    let mut last = HashMap::new();
+    if let Ok(state_guard) = state.lock() {
+        if let Some(store) = state_guard.ip_history.as_ref() {
+            for family in [Family::Ipv4, Family::Ipv6] {
+                match store.latest(family) {
+                    Ok(Some(ip)) => {
+                        last.insert(family, ip);
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Failed to read IP history: {}", e),
+                }
+            }
+        }
+    }
+
+    // 外层 None 表示还没有读取过默认路由，避免启动时探测线程第一次写入被当成变化
+    let mut last_route: Option<Option<String>> = None;
+    let mut next_check = Instant::now();
+    let mut failures = 0u32;
+
+    loop {
+        tokio::time::sleep(IP_WATCH_POLL).await;
+
+        let Some(interval) = settings::with(|s| s.public_ip.watch_interval()) else {
+            continue;
+        };
+        let route = state.lock().ok().and_then(|s| s.default_route_interface.clone());
+        let route_changed = last_route.as_ref().is_some_and(|last| *last != route);
+        last_route = Some(route);
+
+        if !route_changed && Instant::now() < next_check {
+            continue;
+        }
+        if route_changed {
+            debug!("Default route changed, checking public IP");
+        }
+
+        match check_public_ip(&state, &app, &mut last).await {
+            Ok(()) => {
+                failures = 0;
+                next_check = Instant::now() + interval;
+            }
+            Err(e) => {
+                failures += 1;
+                let retry = IP_WATCH_RETRY
+                    .saturating_mul(1 << (failures - 1).min(10))
+                    .min(IP_WATCH_MAX_RETRY);
+                warn!("Public IP check failed ({} in a row), retrying in {}s: {}", failures, retry.as_secs(), e);
+                next_check = Instant::now() + retry;
+            }
+        }
+    }
 }
 
-#[cfg(target_os = "windows")]
-fn ping_gateway() -> (u32, f64) {
-    ping_gateway_internal()
+// 配额中的网卡可以写 id、名称或别名，统一解析为 id；当前不存在的网卡按原样当作 id
+fn resolve_interface_id(interface_stats: &[interfaces::InterfaceStats], entry: &str) -> String {
+    interface_stats
+        .iter()
+        .find(|iface| iface.matches(entry))
+        .map(|iface| iface.id().to_string())
+        .unwrap_or_else(|| entry.to_string())
 }
 
 // Background updater
-fn background_updater(state: Arc<Mutex<NetworkState>>) {
-    log_msg!("Background updater thread started");
+fn background_updater(state: Arc<Mutex<NetworkState>>, app: AppHandle) {
+    info!("Background updater thread started");
 
     loop {
-        thread::sleep(Duration::from_secs(1));
+        thread::sleep(settings::with(|s| s.sampling.sample_interval()));
 
         let mut state_guard = match state.lock() {
             Ok(g) => g,
             Err(_) => {
-                log_msg!("Failed to lock state in background thread");
+                error!("Failed to lock state in background thread");
                 continue;
             }
         };
 
         let now = Instant::now();
 
-        // Get current network bytes
-        let (current_received, current_sent) = get_network_bytes()
-            .unwrap_or((state_guard.cached_received, state_guard.cached_sent));
+        // 采集失败时视为没有网卡，下次成功采集时所有网卡都从 0 速率重新开始
+        let snapshots = collect_interfaces().unwrap_or_default();
 
-        // Calculate speeds
-        let (download_speed, upload_speed) = if let Some(last_time) = state_guard.last_bytes_update {
+        // Calculate speeds from per-interface deltas
+        // counted_bytes 为本次间隔内计入总速率的字节增量，只有重新计算了速率的样本才写入历史
+        let (download_speed, upload_speed, counted_bytes) = if let Some(last_time) = state_guard.last_bytes_update {
             let elapsed = now.duration_since(last_time).as_secs_f64();
 
             if elapsed >= 0.5 {
-                let delta_received = if current_received >= state_guard.last_bytes_received {
-                    current_received - state_guard.last_bytes_received
-                } else {
-                    current_received
-                };
-
-                let delta_sent = if current_sent >= state_guard.last_bytes_sent {
-                    current_sent - state_guard.last_bytes_sent
-                } else {
-                    current_sent
-                };
-
-                let dl_speed = if elapsed > 0.0 {
-                    (delta_received as f64 / elapsed) / 1024.0
-                } else {
-                    0.0
-                };
-
-                let ul_speed = if elapsed > 0.0 {
-                    (delta_sent as f64 / elapsed) / 1024.0
-                } else {
-                    0.0
+                let rates = {
+                    let default_route = state_guard.default_route_interface.as_deref();
+                    let counted = state_guard.interface_filter.counter(&snapshots, default_route);
+                    interfaces::compute_rates(&snapshots, &state_guard.last_counters, elapsed, counted)
                 };
 
-                state_guard.last_bytes_received = current_received;
-                state_guard.last_bytes_sent = current_sent;
+                state_guard.interface_stats = rates.interfaces;
+                state_guard.last_counters = interfaces::counters_by_id(&snapshots);
                 state_guard.last_bytes_update = Some(now);
 
-                (dl_speed.min(1024000.0), ul_speed.min(1024000.0))
+                (rates.download_speed, rates.upload_speed, Some((rates.rx_bytes, rates.tx_bytes)))
             } else {
-                (state_guard.current_stats.download_speed, state_guard.current_stats.upload_speed)
+                (state_guard.current_stats.download_speed, state_guard.current_stats.upload_speed, None)
             }
         } else {
             // First run
-            state_guard.last_bytes_received = current_received;
-            state_guard.last_bytes_sent = current_sent;
+            let rates = {
+                let default_route = state_guard.default_route_interface.as_deref();
+                let counted = state_guard.interface_filter.counter(&snapshots, default_route);
+                interfaces::compute_rates(&snapshots, &HashMap::new(), 0.0, counted)
+            };
+            state_guard.interface_stats = rates.interfaces;
+            state_guard.last_counters = interfaces::counters_by_id(&snapshots);
             state_guard.last_bytes_update = Some(now);
-            (0.0, 0.0)
+            (0.0, 0.0, None)
         };
 
-        state_guard.cached_received = current_received;
-        state_guard.cached_sent = current_sent;
-
-        // Update latency/packet loss every 10 seconds
-        let seconds_since_last_ping = state_guard.last_latency_update
-            .map(|t| t.elapsed().as_secs())
-            .unwrap_or(10);
-
-        let (latency, packet_loss) = if seconds_since_last_ping >= 10 {
-            let (lat, pl) = ping_gateway();
-            state_guard.last_latency_update = Some(now);
-            (lat, pl)
+        // 延迟和丢包率来自探测线程的滑动窗口
+        let summary = state_guard.probe.summary();
+        let latency = if summary.received > 0 {
+            ((summary.avg_rtt_us as f64 / 1000.0).round() as u32).max(1)
         } else {
-            (state_guard.current_stats.latency, state_guard.current_stats.packet_loss)
+            0
         };
+        let packet_loss = summary.packet_loss;
 
-        // Calculate status
-        let status = if latency == 0 && seconds_since_last_ping < 2 {
-            "检测中...".to_string()
-        } else if latency > 100 || packet_loss > 5.0 {
-            "较差".to_string()
-        } else if latency > 50 || packet_loss > 2.0 {
-            "一般".to_string()
+        // 按评分标准计算连接质量
+        let us_to_ms = |us: u64| us as f64 / 1000.0;
+        let (quality, quality_score, r_factor) = if summary.sent == 0 {
+            (quality::ConnectionQuality::Unknown, 0.0, 0.0)
         } else {
-            "良好".to_string()
+            let (avg, jitter) = (us_to_ms(summary.avg_rtt_us), summary.jitter_us / 1000.0);
+            let (quality, score) = state_guard.quality_config.assess(avg, jitter, packet_loss);
+            (quality, score, quality::r_factor(avg, jitter, packet_loss).clamp(0.0, 100.0))
         };
 
         // Update cached stats
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
         state_guard.current_stats = NetworkStats {
+            seq: state_guard.current_stats.seq + 1,
+            timestamp,
+            latency,
+            download_speed,
+            upload_speed,
+            packet_loss,
+            quality,
+            quality_score,
+            r_factor,
+            mos: if summary.sent == 0 { 0.0 } else { quality::mos(r_factor) },
+            jitter: summary.jitter_us / 1000.0,
+            latency_min: us_to_ms(summary.min_rtt_us),
+            latency_max: us_to_ms(summary.max_rtt_us),
+            latency_p50: us_to_ms(summary.p50_rtt_us),
+            latency_p95: us_to_ms(summary.p95_rtt_us),
+        };
+
+        trace!(
+            download_speed,
+            upload_speed,
             latency,
+            p50_us = summary.p50_rtt_us,
+            p95_us = summary.p95_rtt_us,
+            jitter_us = summary.jitter_us,
+            packet_loss,
+            received = summary.received,
+            sent = summary.sent,
+            quality = ?quality,
+            quality_score,
+            "sample"
+        );
+
+        // 要写入数据库的内容在锁内准备好，释放锁后再写
+        let sample = counted_bytes.map(|(rx_bytes, tx_bytes)| history::Sample {
+            timestamp,
             download_speed,
             upload_speed,
+            rx_bytes,
+            tx_bytes,
+            latency: us_to_ms(summary.avg_rtt_us),
             packet_loss,
-            status: status.clone(),
+        });
+        let interface_deltas: Vec<(String, u64, u64)> = match counted_bytes {
+            Some(_) => state_guard
+                .interface_stats
+                .iter()
+                // 与总速率的默认规则一致，回环和离线网卡不记入按网卡的用量
+                .filter(|iface| iface.counts_towards_total())
+                .map(|iface| {
+                    let (rx_bytes, tx_bytes) = iface.delta_bytes();
+                    (iface.id().to_string(), rx_bytes, tx_bytes)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        // 还没有探测结果时延迟和丢包率没有意义，不判断告警
+        let alert_events = if summary.sent > 0 {
+            let metrics = alerts::Metrics {
+                latency: us_to_ms(summary.avg_rtt_us),
+                packet_loss,
+                jitter: summary.jitter_us / 1000.0,
+                download_speed,
+                upload_speed,
+            };
+            let state = &mut *state_guard;
+            state.alert_engine.evaluate(&state.alert_config, &metrics, timestamp)
+        } else {
+            Vec::new()
         };
 
-        log_msg!("BG: DL={:.2} UL={:.2} Lat={}ms PL={:.1} {}",
-            download_speed, upload_speed, latency, packet_loss, status);
+        let quota_check = (state_guard.current_stats.seq % QUOTA_CHECK_SAMPLES == 0).then(|| {
+            (
+                state_guard.quota_config.clone(),
+                state_guard.usage_config.clone(),
+                state_guard.interface_stats.clone(),
+            )
+        });
+        let history_store = state_guard.history.clone();
+        let usage_ledger = state_guard.usage.clone();
+
+        // 释放锁后再写数据库和推送，避免磁盘写入和事件处理阻塞命令
+        let stats = state_guard.current_stats.clone();
+        let subscribers: Vec<String> = state_guard.subscribers.iter().cloned().collect();
+        drop(state_guard);
+
+        if let (Some(sample), Some(store)) = (&sample, &history_store) {
+            match store.lock() {
+                Ok(mut store) => {
+                    if let Err(e) = store.record(sample) {
+                        error!("History: {}", e);
+                    }
+                }
+                Err(_) => error!("Failed to lock history store"),
+            }
+        }
+
+        let mut quota_warnings = Vec::new();
+        if let Some(ledger) = &usage_ledger {
+            match ledger.lock() {
+                Ok(mut ledger) => {
+                    let now = chrono::Local::now();
+                    if let Some(sample) = &sample {
+                        let total = (sample.rx_bytes, sample.tx_bytes);
+                        if let Err(e) = ledger.record(now.date_naive(), total, &interface_deltas) {
+                            error!("Usage: {}", e);
+                        }
+                    }
+
+                    if let Some((quota_config, usage_config, interface_stats)) = &quota_check {
+                        quota_warnings = quota::check(
+                            quota_config,
+                            |entry| resolve_interface_id(interface_stats, entry),
+                            &ledger,
+                            usage_config,
+                            now,
+                        );
+                    }
+                }
+                Err(_) => error!("Failed to lock usage ledger"),
+            }
+        }
+
+        for label in subscribers {
+            if let Err(e) = app.emit_to(label.as_str(), STATS_EVENT, &stats) {
+                error!("Failed to emit {} to {}: {}", STATS_EVENT, label, e);
+            }
+        }
+
+        for alert in alert_events {
+            info!("Alert {} {:?}: {}", alert.rule, alert.state, alert.message);
+            if let Err(e) = app.emit(alerts::ALERT_EVENT, &alert) {
+                error!("Failed to emit {}: {}", alerts::ALERT_EVENT, e);
+            }
+
+            if alert.notify {
+                let title = match alert.state {
+                    alerts::AlertState::Firing => format!("网络告警：{}", alert.rule),
+                    alerts::AlertState::Cleared => format!("告警解除：{}", alert.rule),
+                };
+                if let Err(e) = app.notification().builder().title(title).body(&alert.message).show() {
+                    error!("Failed to show notification: {}", e);
+                }
+            }
+        }
+
+        for warning in quota_warnings {
+            warn!("Quota {} reached {}% ({} / {} bytes)",
+                warning.status.name, warning.threshold, warning.status.used_bytes, warning.status.limit_bytes);
+            if let Err(e) = app.emit(quota::WARNING_EVENT, &warning) {
+                error!("Failed to emit {}: {}", quota::WARNING_EVENT, e);
+            }
+        }
     }
 }
 
@@ -531,200 +887,395 @@ fn get_network_stats(
     state_guard.current_stats.clone()
 }
 
+// 订阅后窗口会在每次计算后收到 network-stats 事件，不需要再轮询 get_network_stats
 #[tauri::command]
-fn test_command() -> String {
-    log_msg!("Test command called!");
-    "Test OK".to_string()
+fn subscribe_network_stats(
+    window: tauri::Window,
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> NetworkStats {
+    let mut state_guard = state.lock().unwrap();
+    if state_guard.subscribers.insert(window.label().to_string()) {
+        debug!("Window {} subscribed to {}", window.label(), STATS_EVENT);
+    }
+    // 返回当前值，订阅后不必等待下一次推送
+    state_guard.current_stats.clone()
 }
 
 #[tauri::command]
-async fn get_public_ip() -> Result<IPInfo, String> {
-    log_msg!("=== Fetching public IP ===");
-
-    // 优先使用能返回地理位置的 JSON API
-    // ip-api.com 免费版无需 API key，但限制 45req/min
-    let apis = [
-        ("http://ip-api.com/json/", "json"),
-        ("https://ipapi.co/json/", "json"),
-        ("https://api.ipify.org?format=json", "json"),
-        ("https://api.ipify.org", "plain"),
-        ("https://ifconfig.me/ip", "plain"),
-        ("http://myip.ipip.net", "chinese"),
-    ];
-
-    // 先尝试 JSON API 获取完整信息
-    for (url, api_type) in apis.iter() {
-        // 跳过非 JSON API，稍后再试
-        if *api_type != "json" {
-            continue;
-        }
+fn unsubscribe_network_stats(
+    window: tauri::Window,
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) {
+    let mut state_guard = state.lock().unwrap();
+    if state_guard.subscribers.remove(window.label()) {
+        debug!("Window {} unsubscribed from {}", window.label(), STATS_EVENT);
+    }
+}
 
-        log_msg!("Trying JSON API: {}", url);
-
-        // 使用 format! 构建脚本，确保 URL 被正确插入
-        let ps_script = format!(r#"
-                try {{
-                    $client = New-Object System.Net.WebClient
-                    $client.Headers.Add("User-Agent", "Mozilla/5.0")
-                    [Net.ServicePointManager]::SecurityProtocol = [Net.SecurityProtocolType]::Tls12
-                    $result = $client.DownloadString("{}")
-                    Write-Output $result
-                }} catch {{
-                    Write-Output "ERROR: $($_.Exception.Message)"
-                }}
-            "#, url);
-
-        match std::process::Command::new("powershell")
-            .args(["-WindowStyle", "Hidden", "-NoProfile", "-NonInteractive", "-Command", &ps_script])
-            .creation_flags(0x08000000)
-            .output() {
-            Ok(result) => {
-                let stdout = String::from_utf8_lossy(&result.stdout);
-                let stderr = String::from_utf8_lossy(&result.stderr);
-                let combined = format!("{}\n{}", stdout, stderr);
-                let trimmed = combined.trim();
-
-                log_msg!("JSON API {} response: '{}'", url, trimmed);
-
-                if trimmed.starts_with("ERROR:") || trimmed.is_empty() {
-                    log_msg!("API {} failed, trying next", url);
-                    continue;
-                }
+// range 为 Unix 毫秒，resolution 为 "1s" / "1m" / "1h" / "auto"
+#[tauri::command]
+fn get_history(
+    range: history::HistoryRange,
+    resolution: history::Resolution,
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> Result<Vec<history::HistoryPoint>, String> {
+    let store = state.lock().unwrap().history.clone().ok_or("历史数据不可用")?;
+    let store = store.lock().unwrap();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    store.query(range, resolution, now)
+}
 
-                // 尝试解析 JSON
-                if let Ok(data) = serde_json::from_str::<serde_json::Value>(&trimmed) {
-                    // 检查是否有错误
-                    if let Some(_status) = data.get("status").and_then(|v| v.as_str()) {
-                        if _status == "fail" {
-                            log_msg!("API returned fail status for {}", url);
-                            continue;
-                        }
-                    }
+// 今日 / 本周 / 本计费周期的流量合计
+#[tauri::command]
+fn get_usage_summary(
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> Result<usage::UsageSummary, String> {
+    let (ledger, usage_config) = {
+        let state_guard = state.lock().unwrap();
+        (state_guard.usage.clone().ok_or("用量数据不可用")?, state_guard.usage_config.clone())
+    };
+    let ledger = ledger.lock().unwrap();
+    ledger.summary(chrono::Local::now().date_naive(), &usage_config)
+}
 
-                    // 提取 IP
-                    let ip = if let Some(v) = data.get("query").or_else(|| data.get("ip")) {
-                        v.as_str().unwrap_or("").to_string()
-                    } else {
-                        continue;
-                    };
+#[tauri::command]
+fn get_usage_config(
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> usage::UsageConfig {
+    let state_guard = state.lock().unwrap();
+    state_guard.usage_config.clone()
+}
 
-                    if ip.is_empty() || !extract_ip(&ip).is_some() {
-                        log_msg!("Invalid IP in JSON response from {}", url);
-                        continue;
-                    }
+#[tauri::command]
+fn set_usage_config(
+    config: usage::UsageConfig,
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> Result<(), String> {
+    config.validate()?;
 
-                    // 提取城市和地区 - 优先使用 region (州/省)
-                    let city = data.get("regionName")
-                        .or_else(|| data.get("region"))
-                        .or_else(|| data.get("city"))
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("Unknown")
-                        .to_string();
+    let mut state_guard = state.lock().unwrap();
 
-                    let country = data.get("country")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("")
-                        .to_string();
+    if let Some(path) = &state_guard.usage_config_path {
+        settings::save_json(&config, path)?;
+    }
 
-                    // 提取时区信息
-                    let timezone = data.get("timezone")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("")
-                        .to_string();
+    info!("Usage config set to {:?}", config);
+    state_guard.usage_config = config;
+    Ok(())
+}
 
-                    log_msg!("Successfully got IP info: {} from {} - city: {}, country: {}, timezone: {}", ip, url, city, country, timezone);
+#[tauri::command]
+fn get_quota_config(
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> quota::QuotaConfig {
+    let state_guard = state.lock().unwrap();
+    state_guard.quota_config.clone()
+}
 
-                    return Ok(IPInfo {
-                        ip,
-                        city,
-                        country,
-                        timezone,
-                    });
-                } else {
-                    log_msg!("Failed to parse JSON from {}", url);
-                }
-            }
-            Err(e) => {
-                log_msg!("PowerShell failed for {}: {}", url, e);
-            }
-        }
+#[tauri::command]
+fn set_quota_config(
+    config: quota::QuotaConfig,
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> Result<(), String> {
+    config.validate()?;
+
+    let mut state_guard = state.lock().unwrap();
+
+    if let Some(path) = &state_guard.quota_config_path {
+        settings::save_json(&config, path)?;
     }
 
-    // JSON API 都失败了，尝试 plain API 只获取 IP
-    log_msg!("JSON APIs failed, trying plain APIs for IP only");
+    info!("Quota config set: {} quotas", config.quotas.len());
+    state_guard.quota_config = config;
+    Ok(())
+}
 
-    for (url, api_type) in apis.iter() {
-        if *api_type == "json" {
-            continue;
-        }
+#[tauri::command]
+fn get_alert_rules(
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> alerts::AlertConfig {
+    let state_guard = state.lock().unwrap();
+    state_guard.alert_config.clone()
+}
 
-        log_msg!("Trying plain API: {}", url);
-
-        let ps_script = match api_type {
-            &"plain" => format!(r#"
-                try {{
-                    $client = New-Object System.Net.WebClient
-                    $client.Headers.Add("User-Agent", "Mozilla/5.0")
-                    [Net.ServicePointManager]::SecurityProtocol = [Net.SecurityProtocolType]::Tls12
-                    $ip = $client.DownloadString("{}")
-                    $ip.Trim()
-                }} catch {{
-                    Write-Host "ERROR: $($_.Exception.Message)"
-                }}
-            "#, url),
-            &"chinese" => format!(r#"
-                try {{
-                    $client = New-Object System.Net.WebClient
-                    $client.Headers.Add("User-Agent", "Mozilla/5.0")
-                    [Net.ServicePointManager]::SecurityProtocol = [Net.SecurityProtocolType]::Tls12
-                    $result = $client.DownloadString("{}")
-                    $result
-                }} catch {{
-                    Write-Host "ERROR: $($_.Exception.Message)"
-                }}
-            "#, url),
-            _ => continue,
-        };
+#[tauri::command]
+fn set_alert_rules(
+    config: alerts::AlertConfig,
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> Result<(), String> {
+    config.validate()?;
 
-        match std::process::Command::new("powershell")
-            .args(["-WindowStyle", "Hidden", "-NoProfile", "-NonInteractive", "-Command", &ps_script])
-            .creation_flags(0x08000000)
-            .output() {
-            Ok(result) => {
-                let stdout = String::from_utf8_lossy(&result.stdout);
-                let stderr = String::from_utf8_lossy(&result.stderr);
-                let combined = format!("{}\n{}", stdout, stderr);
-                let trimmed = combined.trim();
+    let mut state_guard = state.lock().unwrap();
 
-                log_msg!("Plain API {} response: '{}'", url, trimmed);
+    if let Some(path) = &state_guard.alert_config_path {
+        settings::save_json(&config, path)?;
+    }
 
-                if trimmed.starts_with("ERROR:") || trimmed.is_empty() {
-                    continue;
-                }
+    info!("Alert rules set: {} rules", config.rules.len());
+    state_guard.alert_config = config;
+    Ok(())
+}
 
-                if let Some(ip) = extract_ip(&trimmed) {
-                    log_msg!("Got IP from plain API: {} from {}", ip, url);
+#[tauri::command]
+fn get_quality_config(
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> quality::QualityConfig {
+    let state_guard = state.lock().unwrap();
+    state_guard.quality_config
+}
 
-                    return Ok(IPInfo {
-                        ip,
-                        city: "Unknown".to_string(),  // plain API 无法获取城市
-                        country: String::new(),
-                        timezone: String::new(),  // plain API 无法获取时区
-                    });
+#[tauri::command]
+fn set_quality_config(
+    config: quality::QualityConfig,
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> Result<(), String> {
+    config.validate()?;
+
+    let mut state_guard = state.lock().unwrap();
+
+    if let Some(path) = &state_guard.quality_config_path {
+        settings::save_json(&config, path)?;
+    }
+
+    info!("Quality config set to {:?}", config);
+    state_guard.quality_config = config;
+    Ok(())
+}
+
+// 当前日志文件和写入错误，前端据此提示日志不可用
+#[tauri::command]
+fn get_log_status() -> logging::LogStatus {
+    logging::status()
+}
+
+// 设置变化后让日志级别和格式生效，并通知前端重新安排定时器
+fn settings_changed(app: &AppHandle, settings: &settings::Settings) {
+    logging::apply(&settings.log);
+    if let Err(e) = app.emit(settings::CHANGED_EVENT, settings) {
+        error!("Failed to emit settings change: {}", e);
+    }
+}
+
+// 配置目录中的文件被外部修改：重新读取对应的配置，内容有效且变化时替换
+fn config_file_changed(state: &Mutex<NetworkState>, app: &AppHandle, dir: &Path, name: &str) {
+    let path = dir.join(name);
+    match name {
+        settings::FILE_NAME => match settings::read_json::<settings::Settings>(&path) {
+            Ok(loaded) => {
+                if settings::replace(loaded.clone()) {
+                    info!("Settings reloaded");
+                    settings_changed(app, &loaded);
                 }
             }
-            Err(e) => {
-                log_msg!("PowerShell failed for {}: {}", url, e);
-            }
+            // 编辑器写入过程中可能读到不完整的文件，保留当前设置等待下一次事件
+            Err(e) => warn!("Ignoring settings change: {}", e),
+        },
+        interface_filter::FILE_NAME => reload_config_file(state, app, &path, |s| &mut s.interface_filter),
+        usage::CONFIG_FILE_NAME => reload_config_file(state, app, &path, |s| &mut s.usage_config),
+        quota::CONFIG_FILE_NAME => reload_config_file(state, app, &path, |s| &mut s.quota_config),
+        alerts::CONFIG_FILE_NAME => reload_config_file(state, app, &path, |s| &mut s.alert_config),
+        quality::CONFIG_FILE_NAME => reload_config_file(state, app, &path, |s| &mut s.quality_config),
+        _ => {}
+    }
+}
+
+// 通过命令保存时内容与当前配置相同，不会重复发出事件
+fn reload_config_file<T: ConfigFile + PartialEq + std::fmt::Debug>(
+    state: &Mutex<NetworkState>,
+    app: &AppHandle,
+    path: &Path,
+    field: impl FnOnce(&mut NetworkState) -> &mut T,
+) {
+    let loaded = match settings::read_json::<T>(path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            warn!("Ignoring change to {}: {}", path.display(), e);
+            return;
         }
+    };
+
+    {
+        let mut state_guard = state.lock().unwrap();
+        let current = field(&mut state_guard);
+        if *current == loaded {
+            return;
+        }
+        info!("Reloaded {}: {:?}", T::FILE_NAME, loaded);
+        *current = loaded;
+    }
+
+    if let Err(e) = app.emit(settings::CONFIG_CHANGED_EVENT, T::FILE_NAME) {
+        error!("Failed to emit config change: {}", e);
+    }
+}
+
+#[tauri::command]
+fn get_settings() -> settings::Settings {
+    settings::current()
+}
+
+// patch 只需包含要修改的字段，例如 {"ui": {"weather_refresh_minutes": 30}}
+#[tauri::command]
+fn update_settings(
+    patch: serde_json::Value,
+    app: AppHandle,
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> Result<settings::Settings, String> {
+    let updated = settings::current().merged(patch)?;
+
+    // 先取出路径并释放锁，写文件时不阻塞后台线程和其它命令
+    let path = state.lock().unwrap().settings_path.clone();
+    if let Some(path) = &path {
+        settings::save_json(&updated, path)?;
     }
 
-    Err("所有IP API都失败了".to_string())
+    // 保存后文件监听也会收到变化，内容相同时不会重复发出事件
+    if settings::replace(updated.clone()) {
+        info!("Settings updated");
+        settings_changed(&app, &updated);
+    }
+    Ok(updated)
+}
+
+// 各配额当前周期的用量和预计用完时间
+#[tauri::command]
+fn get_quota_status(
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> Result<Vec<quota::QuotaStatus>, String> {
+    let state_guard = state.lock().unwrap();
+    let ledger = state_guard.usage.clone().ok_or("用量数据不可用")?;
+    let ledger = ledger.lock().unwrap();
+    let now = chrono::Local::now();
+
+    state_guard
+        .quota_config
+        .quotas
+        .iter()
+        .map(|q| {
+            let interface_id = q
+                .interface
+                .as_deref()
+                .map(|entry| resolve_interface_id(&state_guard.interface_stats, entry));
+            quota::status(q, interface_id.as_deref(), &ledger, &state_guard.usage_config, now)
+        })
+        .collect()
+}
+
+#[tauri::command]
+fn get_interface_stats(
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> Vec<interfaces::InterfaceStats> {
+    let state_guard = state.lock().unwrap();
+    state_guard.interface_stats.clone()
+}
+
+#[tauri::command]
+fn get_interface_filter(
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> interface_filter::InterfaceFilter {
+    let state_guard = state.lock().unwrap();
+    state_guard.interface_filter.clone()
+}
+
+#[tauri::command]
+fn set_interface_filter(
+    filter: interface_filter::InterfaceFilter,
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> Result<(), String> {
+    let mut state_guard = state.lock().unwrap();
+
+    if let Some(path) = &state_guard.interface_filter_path {
+        settings::save_json(&filter, path)?;
+    }
+
+    info!("Interface filter set to {:?}", filter);
+    state_guard.interface_filter = filter;
+    Ok(())
+}
+
+#[tauri::command]
+fn test_command() -> String {
+    debug!("Test command called!");
+    "Test OK".to_string()
+}
+
+#[tauri::command]
+async fn get_public_ip(health: tauri::State<'_, public_ip::ProviderHealth>) -> Result<public_ip::IPInfo, String> {
+    info!("=== Fetching public IP ===");
+
+    // 接口顺序、超时和离线数据库见设置
+    let (providers, timeout, databases) = settings::with(|s| {
+        (s.public_ip.providers.clone(), s.public_ip.timeout(), s.public_ip.geo_databases.clone())
+    });
+    let client = public_ip::client(timeout, None)?;
+    let info = public_ip::lookup(&client, &providers, &health, None).await?;
+    fill_geo(info, databases).await
+}
+
+// 用离线数据库补齐地理位置；首次查询需要读取整个数据库文件，放到阻塞线程
+async fn fill_geo(mut info: public_ip::IPInfo, databases: Vec<String>) -> Result<public_ip::IPInfo, String> {
+    if databases.is_empty() {
+        return Ok(info);
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        geoip::fill(&mut info, &databases);
+        info
+    })
+    .await
+    .map_err(|e| format!("离线地理位置查询失败: {}", e))
+}
+
+// 分别通过 IPv4 和 IPv6 查询公网地址，判断两个协议的连通性，并列出各网卡的本地地址
+#[tauri::command]
+async fn get_dual_stack(health: tauri::State<'_, public_ip::ProviderHealth>) -> Result<DualStackInfo, String> {
+    use public_ip::Family;
+
+    let (providers, timeout) = settings::with(|s| (s.public_ip.providers.clone(), s.public_ip.timeout()));
+    let ipv4_client = public_ip::client(timeout, Some(Family::Ipv4))?;
+    let ipv6_client = public_ip::client(timeout, Some(Family::Ipv6))?;
+
+    let (ipv4, ipv6) = tokio::join!(
+        public_ip::lookup_family(&ipv4_client, &providers, &health, Family::Ipv4),
+        public_ip::lookup_family(&ipv6_client, &providers, &health, Family::Ipv6),
+    );
+    info!(
+        "Dual stack: IPv4 {:?} (connected: {}), IPv6 {:?} (connected: {})",
+        ipv4.ip, ipv4.connected, ipv6.ip, ipv6.connected
+    );
+
+    // Windows 上可能回退到 PowerShell 读取网卡，放到阻塞线程
+    let interfaces = tauri::async_runtime::spawn_blocking(|| {
+        interfaces::group_addresses(&collect_interfaces().unwrap_or_default(), collect_local_addresses())
+    })
+    .await
+    .map_err(|e| format!("读取网卡地址失败: {}", e))?;
+
+    Ok(DualStackInfo { ipv4, ipv6, interfaces })
+}
+
+// 最近的公网 IP 变化记录，新的在前，默认 100 条
+#[tauri::command]
+fn get_ip_history(
+    limit: Option<u32>,
+    state: tauri::State<Arc<Mutex<NetworkState>>>,
+) -> Result<Vec<ip_history::IpChange>, String> {
+    let state_guard = state.lock().unwrap();
+    let store = state_guard.ip_history.as_ref().ok_or("IP 历史不可用")?;
+    store.recent(limit.unwrap_or(100).min(10_000))
+}
+
+// 按设置中的顺序返回各公网 IP 接口的成功率和冷却状态
+#[tauri::command]
+fn get_ip_provider_stats(health: tauri::State<public_ip::ProviderHealth>) -> Vec<public_ip::ProviderStatus> {
+    settings::with(|s| health.status(&s.public_ip.providers))
 }
 
 #[tauri::command]
 async fn get_weather(city: String, timezone: String) -> Result<WeatherInfo, String> {
-    log_msg!("=== Fetching weather for: {} with timezone: {} ===", city, timezone);
+    info!("=== Fetching weather for: {} with timezone: {} ===", city, timezone);
 
     let url = format!("https://wttr.in/{}?format=j1&lang=zh",
         urlencoding::encode(&city));
@@ -732,7 +1283,7 @@ async fn get_weather(city: String, timezone: String) -> Result<WeatherInfo, Stri
     match reqwest::get(&url).await {
         Ok(response) => {
             let status = response.status();
-            log_msg!("Weather API status: {}", status);
+            debug!("Weather API status: {}", status);
 
             if !status.is_success() {
                 return Err(format!("API返回状态: {}", status));
@@ -808,7 +1359,7 @@ async fn get_weather(city: String, timezone: String) -> Result<WeatherInfo, Stri
                     };
 
                     let icon = get_weather_icon(desc);
-                    log_msg!("Weather: {}°C, {} in {} (region: {}, country: {}, timezone: {}, time: {})",
+                    info!("Weather: {}°C, {} in {} (region: {}, country: {}, timezone: {}, time: {})",
                         temp, desc, location, region, country, timezone, local_time);
 
                     Ok(WeatherInfo {
@@ -821,13 +1372,13 @@ async fn get_weather(city: String, timezone: String) -> Result<WeatherInfo, Stri
                     })
                 }
                 Err(e) => {
-                    log_msg!("JSON parse error: {}", e);
+                    warn!("JSON parse error: {}", e);
                     Err(format!("解析天气数据失败: {}", e))
                 }
             }
         }
         Err(e) => {
-            log_msg!("Weather API request failed: {}", e);
+            warn!("Weather API request failed: {}", e);
             Err(format!("天气 API 请求失败: {}", e))
         }
     }
@@ -931,25 +1482,178 @@ fn get_weather_icon(desc: &str) -> String {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    log_msg!("=== Application started ===");
+    // 先用默认设置安装日志，setup 读取设置文件后再调整级别和格式
+    logging::install(&settings::current().log);
+    info!("=== Application started ===");
 
     let network_state = Arc::new(Mutex::new(NetworkState::default()));
 
-    let state_clone = Arc::clone(&network_state);
-    let handle = thread::spawn(move || {
-        background_updater(state_clone);
+    let prober_state = Arc::clone(&network_state);
+    thread::spawn(move || {
+        latency_prober(prober_state);
     });
 
-    unsafe {
-        BG_THREAD_HANDLE = Some(handle);
-    }
+    let setup_state = Arc::clone(&network_state);
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_notification::init())
+        .setup(move |app| {
+            // 读取设置，以及保存的网卡筛选、计费周期、配额、告警规则和评分标准
+            match app.path().app_config_dir() {
+                Ok(dir) => {
+                    let settings_path = dir.join(settings::FILE_NAME);
+                    match settings::read_json::<settings::Settings>(&settings_path) {
+                        Ok(loaded) => {
+                            logging::apply(&loaded.log);
+                            settings::replace(loaded);
+                        }
+                        Err(e) => warn!("Invalid settings {}, using defaults: {}", settings_path.display(), e),
+                    }
+                    info!("Settings: {}", settings_path.display());
+
+                    // 外部修改设置文件或其它配置文件时立即生效
+                    let watch_handle = app.handle().clone();
+                    let watch_state = Arc::clone(&setup_state);
+                    let watch_dir = dir.clone();
+                    let watcher = settings::watch(dir.clone(), move |name| {
+                        config_file_changed(&watch_state, &watch_handle, &watch_dir, name);
+                    });
+                    let watcher = match watcher {
+                        Ok(watcher) => Some(watcher),
+                        Err(e) => {
+                            warn!("Config live reload disabled: {}", e);
+                            None
+                        }
+                    };
+
+                    let path = dir.join(interface_filter::FILE_NAME);
+                    let filter = settings::load_json::<interface_filter::InterfaceFilter>(&path);
+                    info!("Interface filter: {:?} ({})", filter, path.display());
+
+                    let usage_path = dir.join(usage::CONFIG_FILE_NAME);
+                    let usage_config = settings::load_json::<usage::UsageConfig>(&usage_path);
+
+                    let quota_path = dir.join(quota::CONFIG_FILE_NAME);
+                    let quota_config = settings::load_json::<quota::QuotaConfig>(&quota_path);
+
+                    let alert_path = dir.join(alerts::CONFIG_FILE_NAME);
+                    let alert_config = settings::load_json::<alerts::AlertConfig>(&alert_path);
+
+                    let quality_path = dir.join(quality::CONFIG_FILE_NAME);
+                    let quality_config = settings::load_json::<quality::QualityConfig>(&quality_path);
+
+                    let mut state_guard = setup_state.lock().unwrap();
+                    state_guard.settings_path = Some(settings_path);
+                    state_guard.config_watcher = watcher;
+                    state_guard.interface_filter = filter;
+                    state_guard.interface_filter_path = Some(path);
+                    state_guard.usage_config = usage_config;
+                    state_guard.usage_config_path = Some(usage_path);
+                    state_guard.quota_config = quota_config;
+                    state_guard.quota_config_path = Some(quota_path);
+                    state_guard.alert_config = alert_config;
+                    state_guard.alert_config_path = Some(alert_path);
+                    state_guard.quality_config = quality_config;
+                    state_guard.quality_config_path = Some(quality_path);
+                }
+                Err(e) => error!("Failed to resolve config dir: {}", e),
+            }
+
+            // 日志目录确定后写入启动阶段缓存的日志；设置中指定了 log.path 时优先使用
+            let log_dir = match app.path().app_log_dir() {
+                Ok(dir) => Some(dir),
+                Err(e) => {
+                    error!("Failed to resolve log dir: {}", e);
+                    None
+                }
+            };
+            logging::init(log_dir);
+            info!("Log: {:?}", logging::status().path);
+
+            // 打开历史和用量数据库
+            match app.path().app_data_dir() {
+                Ok(dir) => {
+                    let path = dir.join(history::FILE_NAME);
+                    match history::HistoryStore::open(&path) {
+                        Ok(store) => {
+                            info!("History: {}", path.display());
+                            setup_state.lock().unwrap().history = Some(Arc::new(Mutex::new(store)));
+                        }
+                        Err(e) => warn!("History disabled: {}", e),
+                    }
+
+                    let path = dir.join(usage::FILE_NAME);
+                    match usage::UsageLedger::open(&path) {
+                        Ok(ledger) => {
+                            info!("Usage: {}", path.display());
+                            setup_state.lock().unwrap().usage = Some(Arc::new(Mutex::new(ledger)));
+                        }
+                        Err(e) => warn!("Usage accounting disabled: {}", e),
+                    }
+
+                    let path = dir.join(ip_history::FILE_NAME);
+                    match ip_history::IpHistory::open(&path) {
+                        Ok(store) => {
+                            info!("IP history: {}", path.display());
+                            setup_state.lock().unwrap().ip_history = Some(store);
+                        }
+                        Err(e) => warn!("IP history disabled: {}", e),
+                    }
+                }
+                Err(e) => error!("Failed to resolve data dir: {}", e),
+            }
+
+            // 后台线程需要 AppHandle 推送事件，所以在 setup 中启动
+            let state_clone = Arc::clone(&setup_state);
+            let app_handle = app.handle().clone();
+            let handle = thread::spawn(move || {
+                background_updater(state_clone, app_handle);
+            });
+
+            unsafe {
+                BG_THREAD_HANDLE = Some(handle);
+            }
+
+            tauri::async_runtime::spawn(ip_watcher(Arc::clone(&setup_state), app.handle().clone()));
+
+            Ok(())
+        })
+        .on_window_event(|window, event| {
+            // 窗口关闭后不再向它推送
+            if let tauri::WindowEvent::Destroyed = event {
+                let state = window.state::<Arc<Mutex<NetworkState>>>();
+                let mut state_guard = state.lock().unwrap();
+                state_guard.subscribers.remove(window.label());
+            }
+        })
         .manage(network_state)
+        .manage(public_ip::ProviderHealth::default())
         .invoke_handler(tauri::generate_handler![
             get_network_stats,
+            subscribe_network_stats,
+            unsubscribe_network_stats,
+            get_history,
+            get_usage_summary,
+            get_usage_config,
+            set_usage_config,
+            get_quota_config,
+            set_quota_config,
+            get_quota_status,
+            get_alert_rules,
+            set_alert_rules,
+            get_quality_config,
+            set_quality_config,
+            get_settings,
+            update_settings,
+            get_log_status,
+            get_interface_stats,
+            get_interface_filter,
+            set_interface_filter,
             get_public_ip,
+            get_dual_stack,
+            get_ip_history,
+            get_ip_provider_stats,
             get_weather,
             test_command
         ])
@@ -0,0 +1,432 @@
+// Linux 网络统计：解析 /proc/net/dev、/proc/net/route、/proc/net/ipv6_route 和 /sys/class/net/*
+//
+// 所有读取函数都接受一个根目录参数（正常运行时为 "/"），
+// 这样可以用 fixture 目录代替真实的 procfs/sysfs 来验证解析逻辑。
+// 网卡地址例外：procfs 中没有 IPv4 地址列表，直接调用 getifaddrs。
+
+use crate::interfaces::{InterfaceSnapshot, LocalAddress};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+// net/if.h 中的接口标志
+const IFF_UP: u32 = 0x1;
+const IFF_LOOPBACK: u32 = 0x8;
+const IFF_RUNNING: u32 = 0x40;
+
+// if_arp.h 中的 ARPHRD_LOOPBACK
+const ARPHRD_LOOPBACK: u32 = 772;
+
+// route.h 中的路由标志
+const RTF_UP: u32 = 0x0001;
+const RTF_GATEWAY: u32 = 0x0002;
+const RTF_REJECT: u32 = 0x0200;
+
+pub struct InterfaceCounters {
+    pub name: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+// 解析 /proc/net/dev 的内容
+// 格式：前两行为表头，之后每行 "  eth0: rx_bytes rx_packets ... tx_bytes ..."
+// 接收字段 8 个，发送字段 8 个，tx_bytes 是冒号后的第 9 个字段
+// 内核按 "%6s:%8llu" 输出，名称较长或计数器较大时冒号两侧都没有空格（如 15 个字符的 veth 名称），
+// 不能按空白切分；内核不允许接口名含冒号（dev_valid_name），按冒号切分即可
+pub fn parse_proc_net_dev(text: &str) -> Vec<InterfaceCounters> {
+    let mut result = Vec::new();
+
+    for line in text.lines().skip(2) {
+        let Some(colon) = line.find(':') else {
+            continue;
+        };
+
+        let name = line[..colon].trim();
+        if name.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line[colon + 1..].split_whitespace().collect();
+        if fields.len() < 16 {
+            continue;
+        }
+
+        let (Ok(rx_bytes), Ok(tx_bytes)) = (fields[0].parse::<u64>(), fields[8].parse::<u64>()) else {
+            continue;
+        };
+
+        result.push(InterfaceCounters {
+            name: name.to_string(),
+            rx_bytes,
+            tx_bytes,
+        });
+    }
+
+    result
+}
+
+// 默认路由的下一跳及出口接口
+pub struct Gateway {
+    pub addr: IpAddr,
+    pub interface: String,
+    // 出口接口的 ifindex，ping IPv6 链路本地网关时作为 scope id
+    pub ifindex: u32,
+}
+
+fn is_default_gateway_route(flags: u32) -> bool {
+    flags & RTF_UP != 0 && flags & RTF_GATEWAY != 0 && flags & RTF_REJECT == 0
+}
+
+// 解析 /proc/net/route，返回 metric 最小的默认路由 (接口名, 网关)
+// 地址字段是按主机字节序打印的 __be32，因此用 to_ne_bytes 还原
+pub fn parse_ipv4_default_route(text: &str) -> Option<(String, Ipv4Addr)> {
+    let mut best: Option<(u32, String, Ipv4Addr)> = None;
+
+    for line in text.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 8 {
+            continue;
+        }
+
+        let parse_hex = |s: &str| u32::from_str_radix(s, 16).ok();
+        let (Some(dest), Some(gateway), Some(flags), Ok(metric), Some(mask)) = (
+            parse_hex(fields[1]),
+            parse_hex(fields[2]),
+            parse_hex(fields[3]),
+            fields[6].parse::<u32>(),
+            parse_hex(fields[7]),
+        ) else {
+            continue;
+        };
+
+        if dest != 0 || mask != 0 || gateway == 0 || !is_default_gateway_route(flags) {
+            continue;
+        }
+
+        if best.as_ref().is_none_or(|(m, _, _)| metric < *m) {
+            best = Some((metric, fields[0].to_string(), Ipv4Addr::from(gateway.to_ne_bytes())));
+        }
+    }
+
+    best.map(|(_, iface, addr)| (iface, addr))
+}
+
+// 解析 /proc/net/ipv6_route，返回 metric 最小的默认路由 (接口名, 网关)
+// 每行：dest dest_plen src src_plen next_hop metric refcnt use flags iface，地址为网络字节序十六进制
+pub fn parse_ipv6_default_route(text: &str) -> Option<(String, Ipv6Addr)> {
+    let mut best: Option<(u32, String, Ipv6Addr)> = None;
+
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 10 {
+            continue;
+        }
+
+        let (Ok(dest), Ok(prefix_len), Ok(next_hop), Ok(metric), Ok(flags)) = (
+            u128::from_str_radix(fields[0], 16),
+            u8::from_str_radix(fields[1], 16),
+            u128::from_str_radix(fields[4], 16),
+            u32::from_str_radix(fields[5], 16),
+            u32::from_str_radix(fields[8], 16),
+        ) else {
+            continue;
+        };
+
+        if dest != 0 || prefix_len != 0 || next_hop == 0 || !is_default_gateway_route(flags) {
+            continue;
+        }
+
+        if best.as_ref().is_none_or(|(m, _, _)| metric < *m) {
+            best = Some((metric, fields[9].to_string(), Ipv6Addr::from(next_hop)));
+        }
+    }
+
+    best.map(|(_, iface, addr)| (iface, addr))
+}
+
+// 查找默认网关，优先 IPv4，没有 IPv4 默认路由时使用 IPv6
+pub fn default_gateway(root: &Path) -> Option<Gateway> {
+    let ipv4 = fs::read_to_string(root.join("proc/net/route"))
+        .ok()
+        .and_then(|text| parse_ipv4_default_route(&text))
+        .map(|(iface, addr)| (iface, IpAddr::V4(addr)));
+
+    let (interface, addr) = ipv4.or_else(|| {
+        fs::read_to_string(root.join("proc/net/ipv6_route"))
+            .ok()
+            .and_then(|text| parse_ipv6_default_route(&text))
+            .map(|(iface, addr)| (iface, IpAddr::V6(addr)))
+    })?;
+
+    let ifindex = read_sys_value(root, &interface, "ifindex")
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(0);
+
+    Some(Gateway { addr, interface, ifindex })
+}
+
+fn read_sys_value(root: &Path, name: &str, attr: &str) -> Option<String> {
+    let path = root.join("sys/class/net").join(name).join(attr);
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+// flags 文件是十六进制，例如 "0x1003"
+fn read_flags(root: &Path, name: &str) -> Option<u32> {
+    let raw = read_sys_value(root, name, "flags")?;
+    u32::from_str_radix(raw.trim_start_matches("0x"), 16).ok()
+}
+
+// 与 Windows 的 IF_TYPE_SOFTWARE_LOOPBACK 对应
+pub fn is_loopback(root: &Path, name: &str) -> bool {
+    if let Some(if_type) = read_sys_value(root, name, "type").and_then(|t| t.parse::<u32>().ok()) {
+        if if_type == ARPHRD_LOOPBACK {
+            return true;
+        }
+    }
+
+    read_flags(root, name)
+        .map(|flags| flags & IFF_LOOPBACK != 0)
+        .unwrap_or(name == "lo")
+}
+
+// 与 Windows 的 IF_OPER_STATUS_OPERATIONAL 对应
+// 隧道类接口（tun、wireguard、ppp）的 operstate 常为 "unknown"，
+// 此时退回到 IFF_UP | IFF_RUNNING 判断
+pub fn is_operational(root: &Path, name: &str) -> bool {
+    match read_sys_value(root, name, "operstate").as_deref() {
+        Some("up") => true,
+        Some("unknown") | None => read_flags(root, name)
+            .map(|flags| flags & IFF_UP != 0 && flags & IFF_RUNNING != 0)
+            .unwrap_or(false),
+        Some(_) => false,
+    }
+}
+
+fn interface_type(root: &Path, name: &str) -> &'static str {
+    // 无线网卡在 sysfs 下有 wireless 子目录，type 仍为 ARPHRD_ETHER
+    if root.join("sys/class/net").join(name).join("wireless").exists() {
+        return "wifi";
+    }
+
+    match read_sys_value(root, name, "type").and_then(|t| t.parse::<u32>().ok()) {
+        Some(1) => "ethernet",
+        Some(ARPHRD_LOOPBACK) => "loopback",
+        Some(512) => "ppp",
+        // ipip / sit / gre / ip6tnl 等隧道，以及 tun、wireguard 使用的 ARPHRD_NONE
+        Some(768) | Some(769) | Some(776) | Some(778) | Some(823) | Some(65534) => "tunnel",
+        _ => "other",
+    }
+}
+
+// speed 文件单位为 Mbit/s，未连接或虚拟网卡读出 -1 或读取失败
+fn link_speed(root: &Path, name: &str) -> u64 {
+    read_sys_value(root, name, "speed")
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|&mbps| mbps > 0)
+        .map(|mbps| mbps as u64 * 1_000_000)
+        .unwrap_or(0)
+}
+
+// 读取所有网卡的累计收发字节数及属性
+pub fn read_interfaces(root: &Path) -> Option<Vec<InterfaceSnapshot>> {
+    let text = fs::read_to_string(root.join("proc/net/dev")).ok()?;
+
+    let snapshots: Vec<InterfaceSnapshot> = parse_proc_net_dev(&text)
+        .into_iter()
+        .map(|iface| {
+            let name = iface.name;
+            InterfaceSnapshot {
+                id: name.clone(),
+                alias: read_sys_value(root, &name, "ifalias")
+                    .filter(|alias| !alias.is_empty())
+                    .unwrap_or_else(|| name.clone()),
+                if_type: interface_type(root, &name),
+                mac: read_sys_value(root, &name, "address").unwrap_or_default().to_uppercase(),
+                link_speed: link_speed(root, &name),
+                up: is_operational(root, &name),
+                loopback: is_loopback(root, &name),
+                rx_bytes: iface.rx_bytes,
+                tx_bytes: iface.tx_bytes,
+                name,
+            }
+        })
+        .collect();
+
+    let active_count = snapshots.iter().filter(|s| s.counts_towards_total()).count();
+    tracing::trace!(active_count, "procfs interfaces");
+
+    Some(snapshots)
+}
+
+// 子网掩码中 1 的个数即前缀长度
+fn prefix_len(mask: &[u8]) -> u8 {
+    mask.iter().map(|b| b.count_ones() as u8).sum()
+}
+
+// 通过 getifaddrs 读取各网卡的 IPv4 / IPv6 地址
+pub fn local_addresses() -> Vec<LocalAddress> {
+    let mut addresses = Vec::new();
+    let mut ifaddrs: *mut libc::ifaddrs = std::ptr::null_mut();
+
+    unsafe {
+        if libc::getifaddrs(&mut ifaddrs) != 0 {
+            tracing::warn!("getifaddrs failed: {}", std::io::Error::last_os_error());
+            return addresses;
+        }
+
+        let mut cursor = ifaddrs;
+        while let Some(entry) = cursor.as_ref() {
+            cursor = entry.ifa_next;
+            if entry.ifa_addr.is_null() || entry.ifa_name.is_null() {
+                continue;
+            }
+
+            let name = std::ffi::CStr::from_ptr(entry.ifa_name).to_string_lossy().into_owned();
+            let netmask = entry.ifa_netmask;
+            let (addr, prefix_len) = match i32::from((*entry.ifa_addr).sa_family) {
+                libc::AF_INET => {
+                    let sin = &*(entry.ifa_addr as *const libc::sockaddr_in);
+                    let mask = if netmask.is_null() {
+                        32
+                    } else {
+                        prefix_len(&(*(netmask as *const libc::sockaddr_in)).sin_addr.s_addr.to_ne_bytes())
+                    };
+                    (IpAddr::V4(Ipv4Addr::from(sin.sin_addr.s_addr.to_ne_bytes())), mask)
+                }
+                libc::AF_INET6 => {
+                    let sin6 = &*(entry.ifa_addr as *const libc::sockaddr_in6);
+                    let mask = if netmask.is_null() {
+                        128
+                    } else {
+                        prefix_len(&(*(netmask as *const libc::sockaddr_in6)).sin6_addr.s6_addr)
+                    };
+                    (IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr)), mask)
+                }
+                _ => continue,
+            };
+
+            addresses.push(LocalAddress { interface_id: name, addr, prefix_len });
+        }
+
+        libc::freeifaddrs(ifaddrs);
+    }
+
+    addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture_root() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/linux_net")
+    }
+
+    fn fixture(path: &str) -> String {
+        fs::read_to_string(fixture_root().join(path)).unwrap()
+    }
+
+    #[test]
+    fn proc_net_dev_skips_header_and_malformed_lines() {
+        let counters = parse_proc_net_dev(&fixture("proc/net/dev"));
+        let names: Vec<&str> = counters.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["lo", "eth0", "wlan0", "wg0", "docker0", "veth1a2b3c4d5e6"]);
+    }
+
+    #[test]
+    fn proc_net_dev_reads_rx_and_tx_bytes() {
+        let counters = parse_proc_net_dev(&fixture("proc/net/dev"));
+
+        // 计数器紧贴冒号
+        let eth0 = counters.iter().find(|c| c.name == "eth0").unwrap();
+        assert_eq!((eth0.rx_bytes, eth0.tx_bytes), (12_345_678_901_234, 987_654_321));
+
+        // 15 个字符的接口名，名称、冒号和计数器之间都没有空格
+        let veth = counters.iter().find(|c| c.name == "veth1a2b3c4d5e6").unwrap();
+        assert_eq!((veth.rx_bytes, veth.tx_bytes), (10_000_000_000, 20_000_000_000));
+    }
+
+    // /proc/net/route 中的地址按主机字节序打印，fixture 取自小端机器
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn ipv4_default_route_decodes_gateway_and_prefers_lowest_metric() {
+        let route = parse_ipv4_default_route(&fixture("proc/net/route"));
+        assert_eq!(route, Some(("eth0".to_string(), Ipv4Addr::new(192, 168, 0, 1))));
+    }
+
+    #[test]
+    fn ipv4_default_route_ignores_non_default_and_reject_routes() {
+        let text = "Iface\tDestination\tGateway\tFlags\tRefCnt\tUse\tMetric\tMask\tMTU\tWindow\tIRTT\n\
+                    eth0\t0000A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0\n\
+                    wg0\t00000000\t0100000A\t0201\t0\t0\t0\t00000000\t0\t0\t0\n";
+        assert_eq!(parse_ipv4_default_route(text), None);
+    }
+
+    #[test]
+    fn ipv6_default_route_prefers_lowest_metric() {
+        // eth0 metric 0x400 在前，wlan0 metric 0x258 在后；wg0 metric 更小但是 reject 路由
+        let route = parse_ipv6_default_route(&fixture("proc/net/ipv6_route"));
+        assert_eq!(route, Some(("wlan0".to_string(), "fe80::2".parse().unwrap())));
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn default_gateway_prefers_ipv4_and_reads_ifindex() {
+        let gateway = default_gateway(&fixture_root()).unwrap();
+        assert_eq!(gateway.addr, IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)));
+        assert_eq!(gateway.interface, "eth0");
+        assert_eq!(gateway.ifindex, 2);
+    }
+
+    #[test]
+    fn read_interfaces_applies_sysfs_attributes() {
+        let snapshots = read_interfaces(&fixture_root()).unwrap();
+        let get = |id: &str| snapshots.iter().find(|s| s.id == id).unwrap();
+
+        let eth0 = get("eth0");
+        assert_eq!(eth0.alias, "Uplink");
+        assert_eq!(eth0.if_type, "ethernet");
+        assert_eq!(eth0.mac, "00:11:22:AA:BB:CC");
+        assert_eq!(eth0.link_speed, 1_000_000_000);
+        assert!(eth0.counts_towards_total());
+
+        // speed 为 -1 时链路速率未知
+        let wlan0 = get("wlan0");
+        assert_eq!(wlan0.if_type, "wifi");
+        assert_eq!(wlan0.link_speed, 0);
+        assert_eq!(wlan0.alias, "wlan0");
+
+        // operstate 为 unknown 的隧道按 IFF_UP | IFF_RUNNING 判断
+        let wg0 = get("wg0");
+        assert_eq!(wg0.if_type, "tunnel");
+        assert_eq!(wg0.link_speed, 0);
+        assert!(wg0.up);
+    }
+
+    #[test]
+    fn read_interfaces_excludes_loopback_and_down_interfaces() {
+        let snapshots = read_interfaces(&fixture_root()).unwrap();
+        let get = |id: &str| snapshots.iter().find(|s| s.id == id).unwrap();
+
+        let lo = get("lo");
+        assert!(lo.loopback);
+        assert_eq!(lo.if_type, "loopback");
+        assert!(!lo.counts_towards_total());
+
+        let docker0 = get("docker0");
+        assert!(!docker0.up);
+        assert!(!docker0.counts_towards_total());
+
+        // 没有 sysfs 目录的接口视为离线
+        let veth = get("veth1a2b3c4d5e6");
+        assert!(!veth.up && !veth.loopback);
+
+        let counted: Vec<&str> = snapshots
+            .iter()
+            .filter(|s| s.counts_towards_total())
+            .map(|s| s.id.as_str())
+            .collect();
+        assert_eq!(counted, ["eth0", "wlan0", "wg0"]);
+    }
+}
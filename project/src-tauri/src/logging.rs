@@ -0,0 +1,529 @@
+// 日志：基于 tracing，按级别和模块过滤，输出带时间戳的文本或 JSON 行
+//
+// 日志文件默认在平台日志目录（Tauri 的 app_log_dir），设置中的 log.path 可以指定其他路径。
+// 写文件由单独的线程完成，记录日志的线程（包括每秒采样的后台线程）只把格式化好的行放入队列，不做文件 IO。
+// 日志目录要等 Tauri setup 之后才能确定，在此之前的日志先缓存在写入线程中，init 后写入文件。
+// 文件无法打开时改为输出到 stderr，并记录错误供前端显示，不再静默丢弃。
+//
+// 滚动：文件超过 max_file_mb 或跨天时改名为 network-stats.2026-01-31.log 这样的归档文件
+// （同一天多次滚动时为 network-stats.2026-01-31.1.log），可选 gzip 压缩，只保留最近 max_archives 个。
+// 过滤规则、输出格式和滚动策略都来自设置，修改后立即生效。
+
+use crate::settings::{LogFormat, LogSettings};
+use chrono::{DateTime, Local, NaiveDate};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::thread;
+use tracing_subscriber::filter::{dynamic_filter_fn, EnvFilter};
+use tracing_subscriber::fmt::time::ChronoLocal;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, Layer, Registry};
+
+pub const FILE_NAME: &str = "network-stats.log";
+
+// init 之前最多缓存的日志行数
+const MAX_PENDING: usize = 1000;
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct LogStatus {
+    // 当前使用的日志文件；无法确定日志目录时为 None
+    pub path: Option<String>,
+    // 最近一次写入失败的原因，写入成功后清除
+    pub error: Option<String>,
+}
+
+enum Message {
+    // 格式化好的完整行，已经带换行
+    Line(String),
+    // 日志目录已确定，写入缓存的日志
+    Init,
+}
+
+static SENDER: OnceLock<Sender<Message>> = OnceLock::new();
+static DEFAULT_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+static ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+static JSON: AtomicBool = AtomicBool::new(false);
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+fn log_path() -> Option<PathBuf> {
+    match crate::settings::with(|s| s.log.path.clone()) {
+        Some(path) => Some(PathBuf::from(path)),
+        None => lock(&DEFAULT_DIR).as_ref().map(|dir| dir.join(FILE_NAME)),
+    }
+}
+
+fn send(message: Message) {
+    let sender = SENDER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        let spawned = thread::Builder::new()
+            .name("log-writer".to_string())
+            .spawn(move || LogFile::default().run(receiver));
+        if let Err(e) = spawned {
+            eprintln!("Failed to start log writer: {}", e);
+        }
+        sender
+    });
+
+    // 写入线程不可用时直接输出到 stderr
+    if let Err(mpsc::SendError(Message::Line(line))) = sender.send(message) {
+        eprint!("{}", line);
+    }
+}
+
+struct ActiveFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    // 文件中日志所属的日期，跨天时滚动
+    day: NaiveDate,
+}
+
+#[derive(Default)]
+struct LogFile {
+    initialized: bool,
+    pending: Vec<String>,
+    active: Option<ActiveFile>,
+}
+
+impl LogFile {
+    fn run(mut self, receiver: Receiver<Message>) {
+        for message in receiver {
+            match message {
+                Message::Line(line) if self.initialized => self.write(&line),
+                Message::Line(line) => {
+                    if self.pending.len() < MAX_PENDING {
+                        self.pending.push(line);
+                    }
+                }
+                Message::Init => {
+                    self.initialized = true;
+                    for line in std::mem::take(&mut self.pending) {
+                        self.write(&line);
+                    }
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, line: &str) {
+        let Some(path) = log_path() else {
+            fail(line, "无法确定日志目录".to_string());
+            return;
+        };
+
+        let result = self.prepare(&path, line.len() as u64).and_then(|active| {
+            active.file.write_all(line.as_bytes())?;
+            active.size += line.len() as u64;
+            Ok(())
+        });
+        match result {
+            Ok(()) => *lock(&ERROR) = None,
+            Err(e) => {
+                self.active = None;
+                fail(line, format!("无法写入日志文件 {}: {}", path.display(), e));
+            }
+        }
+    }
+
+    // 返回可以写入 incoming 字节的文件，必要时先打开或滚动
+    fn prepare(&mut self, path: &Path, incoming: u64) -> io::Result<&mut ActiveFile> {
+        let policy = crate::settings::with(|s| s.log.clone());
+        let today = Local::now().date_naive();
+
+        if self.active.as_ref().is_some_and(|active| active.path != path) {
+            self.active = None;
+        }
+        if self.active.is_none() {
+            self.active = Some(open(path, today)?);
+        }
+
+        let rotate = self.active.as_ref().is_some_and(|active| {
+            let too_big = policy.max_file_mb > 0
+                && active.size > 0
+                && active.size + incoming > policy.max_file_mb * 1024 * 1024;
+            let new_day = policy.rotate_daily && active.day != today;
+            too_big || new_day
+        });
+        if rotate {
+            if let Some(active) = self.active.take() {
+                // 先关闭文件，Windows 上打开的文件不能改名
+                drop(active.file);
+                archive(&active.path, active.day, &policy);
+            }
+            self.active = Some(open(path, today)?);
+        }
+
+        match &mut self.active {
+            Some(active) => Ok(active),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+}
+
+fn fail(line: &str, error: String) {
+    // 同一个错误只在 stderr 提示一次
+    let mut last = lock(&ERROR);
+    if last.as_ref() != Some(&error) {
+        eprintln!("{}", error);
+        *last = Some(error);
+    }
+    eprint!("{}", line);
+}
+
+fn open(path: &Path, today: NaiveDate) -> io::Result<ActiveFile> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    // 已有的文件按最后修改日期归属，重启后也能按天滚动
+    let metadata = file.metadata()?;
+    let day = metadata
+        .modified()
+        .map(|time| DateTime::<Local>::from(time).date_naive())
+        .unwrap_or(today);
+
+    Ok(ActiveFile { path: path.to_path_buf(), file, size: metadata.len(), day })
+}
+
+// network-stats.log -> (network-stats, Some(log))
+fn split_name(path: &Path) -> (String, Option<String>) {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = path.extension().map(|s| s.to_string_lossy().into_owned());
+    (stem, ext)
+}
+
+// 同一天的归档依次为 stem.day.ext、stem.day.1.ext、stem.day.2.ext ...（压缩后再加 .gz），
+// 编号取当天已有归档的最大编号加一，清理掉较早的归档后也不会重复使用旧编号
+fn archive_path(path: &Path, day: NaiveDate) -> PathBuf {
+    let (stem, ext) = split_name(path);
+    let ext = ext.map(|ext| format!(".{}", ext)).unwrap_or_default();
+    let prefix = format!("{}.{}", stem, day);
+
+    let existing = path
+        .parent()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let rest = name.strip_prefix(&prefix)?;
+            let rest = rest.strip_suffix(".gz").unwrap_or(rest).strip_suffix(ext.as_str())?;
+            match rest {
+                "" => Some(0),
+                n => n.strip_prefix('.')?.parse::<u32>().ok(),
+            }
+        })
+        .max();
+
+    let name = match existing {
+        None => format!("{}{}", prefix, ext),
+        Some(n) => format!("{}.{}{}", prefix, n + 1, ext),
+    };
+    path.with_file_name(name)
+}
+
+fn archive(path: &Path, day: NaiveDate, policy: &LogSettings) {
+    let target = archive_path(path, day);
+    if let Err(e) = fs::rename(path, &target) {
+        eprintln!("Failed to archive log {}: {}", path.display(), e);
+        return;
+    }
+
+    // 在写入线程中依次完成，期间的日志留在队列中，不影响记录日志的线程
+    if policy.compress {
+        if let Err(e) = gzip(&target) {
+            eprintln!("Failed to compress log {}: {}", target.display(), e);
+        }
+    }
+    prune_archives(path, policy.max_archives);
+}
+
+fn gzip(path: &Path) -> io::Result<()> {
+    let mut target = path.as_os_str().to_owned();
+    target.push(".gz");
+
+    let mut encoder = GzEncoder::new(File::create(&target)?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    fs::remove_file(path)
+}
+
+// 只保留最近 keep 个归档，按修改时间排序
+fn prune_archives(path: &Path, keep: usize) {
+    let Some(dir) = path.parent() else {
+        return;
+    };
+    let (stem, _) = split_name(path);
+    let prefix = format!("{}.", stem);
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    // 归档文件名在前缀之后是日期，据此排除当前日志文件和其他文件
+    let mut archives: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            name.strip_prefix(&prefix)
+                .and_then(|rest| rest.get(..10))
+                .is_some_and(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok())
+        })
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    archives.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+    for (_, old) in archives.into_iter().skip(keep) {
+        if let Err(e) = fs::remove_file(&old) {
+            eprintln!("Failed to remove old log {}: {}", old.display(), e);
+        }
+    }
+}
+
+// fmt 层每条日志调用一次 write_all，内容是完整的一行
+struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        send(Message::Line(String::from_utf8_lossy(buf).into_owned()));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// 过滤规则使用 EnvFilter 语法，例如 "info" 或 "warn,floating_stats_lib::probe=debug"
+pub fn parse_filter(level: &str) -> Result<EnvFilter, String> {
+    EnvFilter::try_new(level).map_err(|e| format!("日志级别无效 {}: {}", level, e))
+}
+
+// 安装全局 subscriber，在程序启动时调用一次；之后的修改通过 apply 生效
+pub fn install(settings: &LogSettings) {
+    let filter = parse_filter(&settings.level).unwrap_or_else(|_| EnvFilter::new("info"));
+    let (filter, handle) = reload::Layer::new(filter);
+    JSON.store(settings.format == LogFormat::Json, Ordering::Relaxed);
+
+    let text = fmt::layer()
+        .with_ansi(false)
+        .with_timer(ChronoLocal::rfc_3339())
+        .with_writer(|| LogWriter)
+        .with_filter(dynamic_filter_fn(|_, _| !JSON.load(Ordering::Relaxed)));
+    let json = fmt::layer()
+        .json()
+        .with_timer(ChronoLocal::rfc_3339())
+        .with_writer(|| LogWriter)
+        .with_filter(dynamic_filter_fn(|_, _| JSON.load(Ordering::Relaxed)));
+
+    match tracing_subscriber::registry().with(filter).with(text).with(json).try_init() {
+        Ok(()) => {
+            let _ = FILTER.set(handle);
+        }
+        Err(e) => eprintln!("Failed to install logger: {}", e),
+    }
+}
+
+pub fn apply(settings: &LogSettings) {
+    JSON.store(settings.format == LogFormat::Json, Ordering::Relaxed);
+
+    let Some(handle) = FILTER.get() else {
+        return;
+    };
+    // 设置在保存前已经校验过，这里失败只可能是手动安装了其他 subscriber
+    match parse_filter(&settings.level).map(|filter| handle.reload(filter)) {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => tracing::error!("Failed to reload log filter: {}", e),
+        Err(e) => tracing::error!("{}", e),
+    }
+}
+
+// 确定默认日志目录并写入之前缓存的日志；default_dir 为 None 时只能使用设置中的路径
+pub fn init(default_dir: Option<PathBuf>) {
+    *lock(&DEFAULT_DIR) = default_dir;
+    send(Message::Init);
+}
+
+pub fn status() -> LogStatus {
+    LogStatus {
+        path: log_path().map(|path| path.display().to_string()),
+        error: lock(&ERROR).clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::{Duration, SystemTime};
+
+    // 每个测试使用独立的临时目录，结束时删除
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("floating-stats-logging-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn touch(&self, name: &str, age_secs: u64) {
+            let file = File::create(self.0.join(name)).unwrap();
+            file.set_modified(SystemTime::now() - Duration::from_secs(age_secs)).unwrap();
+        }
+
+        fn names(&self) -> Vec<String> {
+            let mut names: Vec<String> = fs::read_dir(&self.0)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 1, d).unwrap()
+    }
+
+    fn archive_name(path: &Path, d: u32) -> String {
+        archive_path(path, day(d)).file_name().unwrap().to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn split_name_separates_extension() {
+        assert_eq!(
+            split_name(Path::new("/logs/network-stats.log")),
+            ("network-stats".to_string(), Some("log".to_string()))
+        );
+        assert_eq!(split_name(Path::new("/logs/app")), ("app".to_string(), None));
+    }
+
+    #[test]
+    fn archive_path_numbers_rotations_within_a_day() {
+        let dir = TempDir::new("numbering");
+        let path = dir.0.join(FILE_NAME);
+
+        assert_eq!(archive_name(&path, 31), "network-stats.2026-01-31.log");
+
+        dir.touch("network-stats.2026-01-31.log", 0);
+        assert_eq!(archive_name(&path, 31), "network-stats.2026-01-31.1.log");
+
+        // 压缩后的归档同样计入编号
+        dir.touch("network-stats.2026-01-31.1.log.gz", 0);
+        assert_eq!(archive_name(&path, 31), "network-stats.2026-01-31.2.log");
+
+        // 其它日期的归档不影响编号
+        assert_eq!(archive_name(&path, 30), "network-stats.2026-01-30.log");
+    }
+
+    #[test]
+    fn archive_path_does_not_reuse_pruned_numbers() {
+        let dir = TempDir::new("pruned-numbers");
+        let path = dir.0.join(FILE_NAME);
+
+        // 较早的 .log 和 .1.log 已被清理，只剩 .2.log.gz
+        dir.touch("network-stats.2026-01-31.2.log.gz", 0);
+        assert_eq!(archive_name(&path, 31), "network-stats.2026-01-31.3.log");
+
+        // 前缀相同但不是归档的文件不参与编号
+        dir.touch("network-stats.2026-01-31.old.log", 0);
+        dir.touch("network-stats.2026-01-31.log.bak", 0);
+        assert_eq!(archive_name(&path, 31), "network-stats.2026-01-31.3.log");
+    }
+
+    #[test]
+    fn archive_path_without_extension() {
+        let dir = TempDir::new("no-extension");
+        let path = dir.0.join("app");
+
+        assert_eq!(archive_name(&path, 1), "app.2026-01-01");
+        dir.touch("app.2026-01-01.gz", 0);
+        assert_eq!(archive_name(&path, 1), "app.2026-01-01.1");
+    }
+
+    #[test]
+    fn prune_keeps_newest_archives_only() {
+        let dir = TempDir::new("prune");
+        let path = dir.0.join(FILE_NAME);
+
+        dir.touch("network-stats.2026-01-28.log.gz", 400);
+        dir.touch("network-stats.2026-01-29.log.gz", 300);
+        dir.touch("network-stats.2026-01-30.log.gz", 200);
+        dir.touch("network-stats.2026-01-30.1.log", 100);
+        // 当前日志和其它文件即使更早也不删除
+        dir.touch("network-stats.log", 1000);
+        dir.touch("network-stats.notes.txt", 1000);
+        dir.touch("other.2026-01-01.log", 1000);
+
+        prune_archives(&path, 2);
+        assert_eq!(
+            dir.names(),
+            [
+                "network-stats.2026-01-30.1.log",
+                "network-stats.2026-01-30.log.gz",
+                "network-stats.log",
+                "network-stats.notes.txt",
+                "other.2026-01-01.log",
+            ]
+        );
+    }
+
+    #[test]
+    fn archive_renames_compresses_and_prunes() {
+        let dir = TempDir::new("archive");
+        let path = dir.0.join(FILE_NAME);
+        dir.touch("network-stats.2026-01-29.log.gz", 100);
+        fs::write(&path, "line 1\nline 2\n").unwrap();
+
+        let policy = LogSettings { compress: true, max_archives: 1, ..LogSettings::default() };
+        archive(&path, day(31), &policy);
+
+        // 当前日志改名后压缩，原文件和超出个数的旧归档都被删除
+        assert_eq!(dir.names(), ["network-stats.2026-01-31.log.gz"]);
+
+        let mut text = String::new();
+        flate2::read::GzDecoder::new(File::open(dir.0.join("network-stats.2026-01-31.log.gz")).unwrap())
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "line 1\nline 2\n");
+    }
+
+    #[test]
+    fn archive_without_compression_keeps_plain_file() {
+        let dir = TempDir::new("archive-plain");
+        let path = dir.0.join(FILE_NAME);
+        fs::write(&path, "line\n").unwrap();
+
+        let policy = LogSettings { compress: false, max_archives: 5, ..LogSettings::default() };
+        archive(&path, day(31), &policy);
+        fs::write(&path, "line\n").unwrap();
+        archive(&path, day(31), &policy);
+
+        assert_eq!(dir.names(), ["network-stats.2026-01-31.1.log", "network-stats.2026-01-31.log"]);
+    }
+}
@@ -0,0 +1,546 @@
+// 进程内延迟探测，统一返回类型化结果，不再解析 ping.exe 的本地化输出
+//
+// Windows：IcmpSendEcho（IPv4）、Icmp6SendEcho2（IPv6）
+// Linux：优先使用非特权 ICMP 套接字（SOCK_DGRAM + IPPROTO_ICMP，受 net.ipv4.ping_group_range 控制），
+//        不允许时退回 raw socket（需要 CAP_NET_RAW）
+// 其它平台或 ICMP 不可用时：用 TCP 连接耗时近似往返时间（Windows 两种协议都有 ICMP API，不需要回退）
+
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+#[cfg(not(target_os = "windows"))]
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, SocketAddrV6, TcpStream},
+    time::Instant,
+};
+
+// 网关上通常只开着 DNS 或管理页面；即使端口关闭，RST 也能给出一次往返时间
+#[cfg(not(target_os = "windows"))]
+const TCP_FALLBACK_PORTS: [u16; 3] = [53, 80, 443];
+
+#[derive(Debug, Clone, Copy)]
+pub struct EchoReply {
+    pub rtt_us: u64,
+    // TCP 回退和 Windows ICMPv6 拿不到 TTL
+    pub ttl: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EchoError {
+    Timeout,
+    NetUnreachable,
+    HostUnreachable,
+    // 其它不可达原因（协议、端口、管理禁止等）
+    Unreachable,
+    TtlExpired,
+    // 系统调用失败或无法识别的状态
+    Failed(String),
+}
+
+impl fmt::Display for EchoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EchoError::Timeout => write!(f, "timed out"),
+            EchoError::NetUnreachable => write!(f, "network unreachable"),
+            EchoError::HostUnreachable => write!(f, "host unreachable"),
+            EchoError::Unreachable => write!(f, "destination unreachable"),
+            EchoError::TtlExpired => write!(f, "TTL expired in transit"),
+            EchoError::Failed(msg) => write!(f, "failed: {}", msg),
+        }
+    }
+}
+
+pub type EchoResult = Result<EchoReply, EchoError>;
+
+// IPv6 链路本地地址（如 fe80:: 网关）必须带 scope id 才能发送
+#[cfg(not(target_os = "windows"))]
+fn socket_addr(target: IpAddr, port: u16, scope_id: u32) -> SocketAddr {
+    match target {
+        IpAddr::V6(v6) => SocketAddr::V6(SocketAddrV6::new(v6, port, 0, scope_id)),
+        IpAddr::V4(_) => SocketAddr::new(target, port),
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn io_error_to_echo(e: &std::io::Error) -> EchoError {
+    match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => EchoError::Timeout,
+        ErrorKind::NetworkUnreachable => EchoError::NetUnreachable,
+        ErrorKind::HostUnreachable => EchoError::HostUnreachable,
+        _ => EchoError::Failed(e.to_string()),
+    }
+}
+
+// 以 TCP 握手耗时近似 RTT：连接成功或被拒绝（RST）都说明对端可达
+#[cfg(not(target_os = "windows"))]
+fn tcp_connect_timing(target: IpAddr, scope_id: u32, timeout: Duration) -> EchoResult {
+    let deadline = Instant::now() + timeout;
+    let mut last_error = EchoError::Timeout;
+
+    for port in TCP_FALLBACK_PORTS {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+
+        let start = Instant::now();
+        match TcpStream::connect_timeout(&socket_addr(target, port, scope_id), remaining) {
+            Ok(_) => {
+                return Ok(EchoReply { rtt_us: start.elapsed().as_micros() as u64, ttl: None });
+            }
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                return Ok(EchoReply { rtt_us: start.elapsed().as_micros() as u64, ttl: None });
+            }
+            Err(e) => last_error = io_error_to_echo(&e),
+        }
+    }
+
+    Err(last_error)
+}
+
+// 向目标发送一次 echo；scope_id 仅对 IPv6 链路本地地址有意义，其余情况传 0
+#[cfg(target_os = "windows")]
+pub fn echo(target: IpAddr, scope_id: u32, timeout: Duration) -> EchoResult {
+    match target {
+        IpAddr::V4(v4) => windows_icmp::echo_v4(v4, timeout),
+        IpAddr::V6(v6) => windows_icmp::echo_v6(v6, scope_id, timeout),
+    }
+}
+
+// 记住可用的探测方式：套接字一旦因权限创建失败就不再重试，避免每次探测都刷日志
+// 按地址族分开记录（下标 0 为 IPv4，1 为 IPv6）：例如内核禁用 IPv6 时 ICMPv6 套接字创建失败，
+// 不应让 IPv4 探测也退回 TCP
+#[cfg(target_os = "linux")]
+static LINUX_METHOD: [std::sync::atomic::AtomicU8; 2] = [
+    std::sync::atomic::AtomicU8::new(METHOD_DGRAM),
+    std::sync::atomic::AtomicU8::new(METHOD_DGRAM),
+];
+#[cfg(target_os = "linux")]
+const METHOD_DGRAM: u8 = 0;
+#[cfg(target_os = "linux")]
+const METHOD_RAW: u8 = 1;
+#[cfg(target_os = "linux")]
+const METHOD_TCP: u8 = 2;
+
+#[cfg(target_os = "linux")]
+fn family_name(target: &IpAddr) -> &'static str {
+    if target.is_ipv4() {
+        "IPv4"
+    } else {
+        "IPv6"
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn echo(target: IpAddr, scope_id: u32, timeout: Duration) -> EchoResult {
+    use socket2::Type;
+    use std::sync::atomic::Ordering;
+
+    let method = &LINUX_METHOD[target.is_ipv6() as usize];
+    if method.load(Ordering::Relaxed) == METHOD_DGRAM {
+        match linux_icmp::open_socket(&target, Type::DGRAM) {
+            Ok(socket) => match linux_icmp::echo(&socket, target, scope_id, false, timeout) {
+                Err(EchoError::Failed(msg)) => tracing::warn!("ICMP dgram echo failed: {}", msg),
+                result => return result,
+            },
+            Err(e) => {
+                tracing::warn!("ICMP dgram socket unavailable for {}: {}", family_name(&target), e);
+                method.store(METHOD_RAW, Ordering::Relaxed);
+            }
+        }
+    }
+
+    if method.load(Ordering::Relaxed) <= METHOD_RAW {
+        match linux_icmp::open_socket(&target, Type::RAW) {
+            Ok(socket) => match linux_icmp::echo(&socket, target, scope_id, true, timeout) {
+                Err(EchoError::Failed(msg)) => tracing::warn!("ICMP raw echo failed: {}", msg),
+                result => return result,
+            },
+            Err(e) => {
+                tracing::warn!(
+                    "ICMP raw socket unavailable for {}: {}, falling back to TCP connect timing",
+                    family_name(&target),
+                    e
+                );
+                method.store(METHOD_TCP, Ordering::Relaxed);
+            }
+        }
+    }
+
+    tcp_connect_timing(target, scope_id, timeout)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn echo(target: IpAddr, scope_id: u32, timeout: Duration) -> EchoResult {
+    tcp_connect_timing(target, scope_id, timeout)
+}
+
+#[cfg(target_os = "windows")]
+mod windows_icmp {
+    use super::{EchoError, EchoReply, EchoResult};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::time::{Duration, Instant};
+    use windows::Win32::Foundation::GetLastError;
+    use windows::Win32::NetworkManagement::IpHelper::{
+        Icmp6CreateFile, Icmp6ParseReplies, Icmp6SendEcho2, IcmpCloseHandle, IcmpCreateFile, IcmpSendEcho,
+        ICMPV6_ECHO_REPLY_LH, ICMP_ECHO_REPLY, IP_DEST_HOST_UNREACHABLE, IP_DEST_NET_UNREACHABLE,
+        IP_DEST_PORT_UNREACHABLE, IP_DEST_PROT_UNREACHABLE, IP_DEST_UNREACHABLE, IP_REQ_TIMED_OUT, IP_SUCCESS,
+        IP_TTL_EXPIRED_REASSEM, IP_TTL_EXPIRED_TRANSIT,
+    };
+    use windows::Win32::Networking::WinSock::{AF_INET6, SOCKADDR_IN6};
+
+    const PAYLOAD: [u8; 32] = *b"abcdefghijklmnopqrstuvwabcdefghi";
+
+    // 回复缓冲区需容纳回复结构 + 数据 + 8 字节 ICMP 错误信息 + IO_STATUS_BLOCK
+    const REPLY_EXTRA: usize = PAYLOAD.len() + 8 + 16;
+
+    // 没有收到回复：只有 IP_REQ_TIMED_OUT 是超时，其它（缓冲区不足、参数错误等）是调用失败
+    fn send_error(function: &str, code: u32) -> EchoError {
+        match code {
+            IP_REQ_TIMED_OUT => EchoError::Timeout,
+            code => EchoError::Failed(format!("{} error {}", function, code)),
+        }
+    }
+
+    // IPv6 的状态码与 IPv4 共用数值：IP_DEST_NO_ROUTE = IP_DEST_NET_UNREACHABLE，
+    // IP_DEST_ADDR_UNREACHABLE = IP_DEST_HOST_UNREACHABLE，IP_DEST_PROHIBITED = IP_DEST_PROT_UNREACHABLE，
+    // IP_HOP_LIMIT_EXCEEDED = IP_TTL_EXPIRED_TRANSIT
+    fn reply_result(status: u32, elapsed: Duration, round_trip_ms: u32, ttl: Option<u8>) -> EchoResult {
+        match status {
+            IP_SUCCESS => {
+                // RoundTripTime 只有毫秒精度，本地计时更精确，但不应小于它
+                let rtt_us = (elapsed.as_micros() as u64).max(round_trip_ms as u64 * 1000);
+                Ok(EchoReply { rtt_us, ttl })
+            }
+            IP_REQ_TIMED_OUT => Err(EchoError::Timeout),
+            IP_DEST_NET_UNREACHABLE => Err(EchoError::NetUnreachable),
+            IP_DEST_HOST_UNREACHABLE => Err(EchoError::HostUnreachable),
+            IP_DEST_PROT_UNREACHABLE | IP_DEST_PORT_UNREACHABLE | IP_DEST_UNREACHABLE => Err(EchoError::Unreachable),
+            IP_TTL_EXPIRED_TRANSIT | IP_TTL_EXPIRED_REASSEM => Err(EchoError::TtlExpired),
+            status => Err(EchoError::Failed(format!("IP status {}", status))),
+        }
+    }
+
+    pub fn echo_v4(target: Ipv4Addr, timeout: Duration) -> EchoResult {
+        let mut reply_buf = vec![0u8; std::mem::size_of::<ICMP_ECHO_REPLY>() + REPLY_EXTRA];
+
+        unsafe {
+            let handle = IcmpCreateFile().map_err(|e| EchoError::Failed(e.to_string()))?;
+
+            let start = Instant::now();
+            let count = IcmpSendEcho(
+                handle,
+                u32::from_ne_bytes(target.octets()),
+                PAYLOAD.as_ptr() as _,
+                PAYLOAD.len() as u16,
+                None,
+                reply_buf.as_mut_ptr() as _,
+                reply_buf.len() as u32,
+                timeout.as_millis() as u32,
+            );
+            let elapsed = start.elapsed();
+            // 必须在 IcmpCloseHandle 之前读取，否则错误码可能被覆盖
+            let last_error = GetLastError();
+            let _ = IcmpCloseHandle(handle);
+
+            if count == 0 {
+                return Err(send_error("IcmpSendEcho", last_error.0));
+            }
+
+            let reply = std::ptr::read_unaligned(reply_buf.as_ptr() as *const ICMP_ECHO_REPLY);
+            reply_result(reply.Status, elapsed, reply.RoundTripTime, Some(reply.Options.Ttl))
+        }
+    }
+
+    // scope_id 用于 fe80:: 链路本地网关；ICMPv6 回复中没有跳数限制，ttl 为 None
+    pub fn echo_v6(target: Ipv6Addr, scope_id: u32, timeout: Duration) -> EchoResult {
+        let mut reply_buf = vec![0u8; std::mem::size_of::<ICMPV6_ECHO_REPLY_LH>() + REPLY_EXTRA];
+
+        unsafe {
+            // 源地址为未指定地址，由系统按路由选择
+            let mut source: SOCKADDR_IN6 = std::mem::zeroed();
+            source.sin6_family = AF_INET6;
+            let mut destination = source;
+            destination.sin6_addr.u.Byte = target.octets();
+            destination.Anonymous.sin6_scope_id = scope_id;
+
+            let handle = Icmp6CreateFile().map_err(|e| EchoError::Failed(e.to_string()))?;
+
+            let start = Instant::now();
+            let count = Icmp6SendEcho2(
+                handle,
+                None,
+                None,
+                None,
+                &source,
+                &destination,
+                PAYLOAD.as_ptr() as _,
+                PAYLOAD.len() as u16,
+                None,
+                reply_buf.as_mut_ptr() as _,
+                reply_buf.len() as u32,
+                timeout.as_millis() as u32,
+            );
+            let elapsed = start.elapsed();
+            let last_error = GetLastError();
+            let _ = IcmpCloseHandle(handle);
+
+            if count == 0 {
+                return Err(send_error("Icmp6SendEcho2", last_error.0));
+            }
+            if Icmp6ParseReplies(reply_buf.as_mut_ptr() as _, reply_buf.len() as u32) == 0 {
+                return Err(send_error("Icmp6ParseReplies", GetLastError().0));
+            }
+
+            let reply = std::ptr::read_unaligned(reply_buf.as_ptr() as *const ICMPV6_ECHO_REPLY_LH);
+            reply_result(reply.Status, elapsed, reply.RoundTripTime, None)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux_icmp {
+    use super::{io_error_to_echo, socket_addr, EchoError, EchoReply, EchoResult};
+    use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+    use std::net::IpAddr;
+    use std::os::fd::AsRawFd;
+    use std::sync::atomic::{AtomicU16, Ordering};
+    use std::time::{Duration, Instant};
+
+    const ICMP_ECHO_REQUEST: u8 = 8;
+    const ICMP_ECHO_REPLY: u8 = 0;
+    const ICMP_DEST_UNREACH: u8 = 3;
+    const ICMP_TIME_EXCEEDED: u8 = 11;
+    const ICMPV6_ECHO_REQUEST: u8 = 128;
+    const ICMPV6_ECHO_REPLY: u8 = 129;
+    const ICMPV6_DEST_UNREACH: u8 = 1;
+    const ICMPV6_TIME_EXCEEDED: u8 = 3;
+
+    const IPV6_HEADER_LEN: usize = 40;
+    const PAYLOAD_LEN: usize = 32;
+
+    static SEQUENCE: AtomicU16 = AtomicU16::new(0);
+
+    // RFC 1071 校验和
+    fn checksum(data: &[u8]) -> u16 {
+        let mut sum = 0u32;
+        for chunk in data.chunks(2) {
+            let word = if chunk.len() == 2 {
+                u16::from_be_bytes([chunk[0], chunk[1]])
+            } else {
+                u16::from_be_bytes([chunk[0], 0])
+            };
+            sum += word as u32;
+        }
+        while sum >> 16 != 0 {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+
+    fn build_echo_request(target: &IpAddr, ident: u16, seq: u16) -> Vec<u8> {
+        let mut packet = vec![0u8; 8 + PAYLOAD_LEN];
+        packet[0] = if target.is_ipv4() { ICMP_ECHO_REQUEST } else { ICMPV6_ECHO_REQUEST };
+        packet[4..6].copy_from_slice(&ident.to_be_bytes());
+        packet[6..8].copy_from_slice(&seq.to_be_bytes());
+        for (i, byte) in packet[8..].iter_mut().enumerate() {
+            *byte = b'a' + (i % 26) as u8;
+        }
+
+        // ICMPv6 的校验和包含伪首部，由内核计算
+        if target.is_ipv4() {
+            let sum = checksum(&packet);
+            packet[2..4].copy_from_slice(&sum.to_be_bytes());
+        }
+
+        packet
+    }
+
+    fn set_int_option(socket: &Socket, level: libc::c_int, name: libc::c_int) -> std::io::Result<()> {
+        let value: libc::c_int = 1;
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                level,
+                name,
+                &value as *const _ as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn open_socket(target: &IpAddr, ty: Type) -> std::io::Result<Socket> {
+        let (domain, protocol) = match target {
+            IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+            IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+        };
+        let socket = Socket::new(domain, ty, Some(protocol))?;
+
+        // 让内核在 cmsg 中附带 TTL / hop limit
+        match target {
+            IpAddr::V4(_) => set_int_option(&socket, libc::IPPROTO_IP, libc::IP_RECVTTL)?,
+            IpAddr::V6(_) => set_int_option(&socket, libc::IPPROTO_IPV6, libc::IPV6_RECVHOPLIMIT)?,
+        }
+
+        Ok(socket)
+    }
+
+    // recvmsg 并从控制消息中取出 TTL（IPv4）或 hop limit（IPv6）
+    fn recv_with_ttl(socket: &Socket, buf: &mut [u8]) -> std::io::Result<(usize, Option<u8>)> {
+        let mut control = [0u64; 8];
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+        let len = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+        if len < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let mut ttl = None;
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                let header = &*cmsg;
+                let is_ttl = (header.cmsg_level == libc::IPPROTO_IP && header.cmsg_type == libc::IP_TTL)
+                    || (header.cmsg_level == libc::IPPROTO_IPV6 && header.cmsg_type == libc::IPV6_HOPLIMIT);
+                if is_ttl {
+                    let value = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+                    ttl = Some(value as u8);
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+
+        Ok((len as usize, ttl))
+    }
+
+    // 判断 ICMP 差错报文中携带的原始报文是否是我们发出的 echo
+    fn quotes_our_request(original: &[u8], request_type: u8, ident: u16, seq: u16) -> bool {
+        original.len() >= 8
+            && original[0] == request_type
+            && u16::from_be_bytes([original[4], original[5]]) == ident
+            && u16::from_be_bytes([original[6], original[7]]) == seq
+    }
+
+    fn classify_error_v4(reply: &[u8], ident: u16, seq: u16) -> Option<EchoError> {
+        if reply.len() < 8 + 20 {
+            return None;
+        }
+        let quoted_ip = &reply[8..];
+        let header_len = ((quoted_ip[0] & 0x0f) as usize) * 4;
+        if quoted_ip.len() < header_len || !quotes_our_request(&quoted_ip[header_len..], ICMP_ECHO_REQUEST, ident, seq) {
+            return None;
+        }
+
+        match (reply[0], reply[1]) {
+            (ICMP_DEST_UNREACH, 0) => Some(EchoError::NetUnreachable),
+            (ICMP_DEST_UNREACH, 1) => Some(EchoError::HostUnreachable),
+            (ICMP_DEST_UNREACH, _) => Some(EchoError::Unreachable),
+            (ICMP_TIME_EXCEEDED, _) => Some(EchoError::TtlExpired),
+            _ => None,
+        }
+    }
+
+    fn classify_error_v6(reply: &[u8], ident: u16, seq: u16) -> Option<EchoError> {
+        if reply.len() < 8 + IPV6_HEADER_LEN
+            || !quotes_our_request(&reply[8 + IPV6_HEADER_LEN..], ICMPV6_ECHO_REQUEST, ident, seq)
+        {
+            return None;
+        }
+
+        match (reply[0], reply[1]) {
+            (ICMPV6_DEST_UNREACH, 0) => Some(EchoError::NetUnreachable),
+            (ICMPV6_DEST_UNREACH, 3) => Some(EchoError::HostUnreachable),
+            (ICMPV6_DEST_UNREACH, _) => Some(EchoError::Unreachable),
+            (ICMPV6_TIME_EXCEEDED, _) => Some(EchoError::TtlExpired),
+            _ => None,
+        }
+    }
+
+    // 发送一次 echo 并等待对应的 reply 或差错报文
+    pub fn echo(socket: &Socket, target: IpAddr, scope_id: u32, raw: bool, timeout: Duration) -> EchoResult {
+        let ident = std::process::id() as u16;
+        let seq = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+        let packet = build_echo_request(&target, ident, seq);
+        let addr = SockAddr::from(socket_addr(target, 0, scope_id));
+
+        let start = Instant::now();
+        let deadline = start + timeout;
+        socket.send_to(&packet, &addr).map_err(|e| io_error_to_echo(&e))?;
+
+        let expected_type = if target.is_ipv4() { ICMP_ECHO_REPLY } else { ICMPV6_ECHO_REPLY };
+        let mut buf = [0u8; 1500];
+
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(EchoError::Timeout);
+            }
+            socket
+                .set_read_timeout(Some(deadline - now))
+                .map_err(|e| EchoError::Failed(e.to_string()))?;
+
+            let (len, cmsg_ttl) = match recv_with_ttl(socket, &mut buf) {
+                Ok(received) => received,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(io_error_to_echo(&e)),
+            };
+            let elapsed = start.elapsed();
+
+            // IPv4 raw socket 收到的数据带 IP 首部，TTL 在首部第 9 字节
+            let (reply, header_ttl) = if raw && target.is_ipv4() {
+                let header_len = ((buf[0] & 0x0f) as usize) * 4;
+                if len < header_len || header_len < 20 {
+                    continue;
+                }
+                (&buf[header_len..len], Some(buf[8]))
+            } else {
+                (&buf[..len], None)
+            };
+
+            if reply.len() < 8 {
+                continue;
+            }
+
+            // dgram 套接字收不到差错报文（内核放进错误队列），只有 raw 模式需要处理
+            if raw && reply[0] != expected_type {
+                let error = if target.is_ipv4() {
+                    classify_error_v4(reply, ident, seq)
+                } else {
+                    classify_error_v6(reply, ident, seq)
+                };
+                match error {
+                    Some(error) => return Err(error),
+                    None => continue,
+                }
+            }
+
+            if reply[0] != expected_type || u16::from_be_bytes([reply[6], reply[7]]) != seq {
+                continue;
+            }
+            // dgram 套接字的 identifier 会被内核改写为本地端口，只在 raw 模式下校验
+            if raw && u16::from_be_bytes([reply[4], reply[5]]) != ident {
+                continue;
+            }
+
+            return Ok(EchoReply {
+                rtt_us: elapsed.as_micros() as u64,
+                ttl: cmsg_ttl.or(header_ttl),
+            });
+        }
+    }
+}
@@ -0,0 +1,209 @@
+// 延迟探测会话：保存最近 N 次探测结果，统计真实丢包率、RTT 最小/平均/最大值和抖动
+//
+// 旧实现每 10 秒只发一个 echo，丢包率只能是 0% 或 100%；
+// 现在由独立线程按固定间隔持续探测，丢包率按滑动窗口内的比例计算。
+
+use crate::ping::EchoResult;
+use std::collections::VecDeque;
+
+// 默认统计最近 60 次（每秒一次即一分钟），探测间隔和超时见 settings
+pub const DEFAULT_WINDOW: usize = 60;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ProbeSummary {
+    pub sent: u32,
+    pub received: u32,
+    pub packet_loss: f64,
+    pub min_rtt_us: u64,
+    pub avg_rtt_us: u64,
+    pub max_rtt_us: u64,
+    pub p50_rtt_us: u64,
+    pub p95_rtt_us: u64,
+    pub jitter_us: f64,
+}
+
+pub struct ProbeSession {
+    window: usize,
+    // None 表示该次探测丢失
+    results: VecDeque<Option<u64>>,
+}
+
+impl Default for ProbeSession {
+    fn default() -> Self {
+        ProbeSession::new(DEFAULT_WINDOW)
+    }
+}
+
+impl ProbeSession {
+    pub fn new(window: usize) -> Self {
+        let window = window.max(1);
+        ProbeSession {
+            window,
+            results: VecDeque::with_capacity(window),
+        }
+    }
+
+    pub fn record(&mut self, result: &EchoResult) {
+        if self.results.len() == self.window {
+            self.results.pop_front();
+        }
+        self.results.push_back(result.as_ref().ok().map(|reply| reply.rtt_us));
+    }
+
+    // 设置中的窗口大小变化时调整，缩小时丢弃最旧的结果
+    pub fn set_window(&mut self, window: usize) {
+        self.window = window.max(1);
+        while self.results.len() > self.window {
+            self.results.pop_front();
+        }
+    }
+
+    // 探测目标变化（如切换网络）时清空，避免新旧网关的数据混在一起
+    pub fn clear(&mut self) {
+        self.results.clear();
+    }
+
+    pub fn summary(&self) -> ProbeSummary {
+        let sent = self.results.len() as u32;
+        let rtts: Vec<u64> = self.results.iter().flatten().copied().collect();
+        let received = rtts.len() as u32;
+
+        if received == 0 {
+            return ProbeSummary {
+                sent,
+                received,
+                packet_loss: if sent == 0 { 0.0 } else { 100.0 },
+                ..Default::default()
+            };
+        }
+
+        // 抖动按到达顺序计算，分位数需要排序后的副本
+        let jitter_us = rfc3550_jitter(&rtts);
+        let mut sorted = rtts;
+        sorted.sort_unstable();
+
+        ProbeSummary {
+            sent,
+            received,
+            packet_loss: (sent - received) as f64 * 100.0 / sent as f64,
+            min_rtt_us: sorted[0],
+            avg_rtt_us: sorted.iter().sum::<u64>() / received as u64,
+            max_rtt_us: sorted[sorted.len() - 1],
+            p50_rtt_us: percentile(&sorted, 50.0),
+            p95_rtt_us: percentile(&sorted, 95.0),
+            jitter_us,
+        }
+    }
+}
+
+// RFC 3550 到达间隔抖动：J += (|D(i-1,i)| - J) / 16
+// 对 echo 而言 D 就是相邻两次 RTT 之差
+pub fn rfc3550_jitter(rtts: &[u64]) -> f64 {
+    let mut jitter = 0.0;
+    for pair in rtts.windows(2) {
+        let d = (pair[1] as f64 - pair[0] as f64).abs();
+        jitter += (d - jitter) / 16.0;
+    }
+    jitter
+}
+
+// 最近秩法（nearest-rank）分位数，sorted 必须非空且已升序
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ping::{EchoError, EchoReply};
+
+    fn reply(rtt_us: u64) -> EchoResult {
+        Ok(EchoReply { rtt_us, ttl: Some(64) })
+    }
+
+    #[test]
+    fn jitter_is_zero_for_constant_or_single_rtt() {
+        assert_eq!(rfc3550_jitter(&[]), 0.0);
+        assert_eq!(rfc3550_jitter(&[5000]), 0.0);
+        assert_eq!(rfc3550_jitter(&[5000, 5000, 5000]), 0.0);
+    }
+
+    #[test]
+    fn jitter_follows_rfc3550_smoothing() {
+        // 第一次差值 1600：J = 1600 / 16 = 100；第二次差值 1600：J = 100 + (1600 - 100) / 16
+        let jitter = rfc3550_jitter(&[1000, 2600, 1000]);
+        assert!((jitter - 193.75).abs() < 1e-9);
+
+        // 只看相邻差值的绝对值，与方向无关
+        assert_eq!(rfc3550_jitter(&[1000, 2600]), rfc3550_jitter(&[2600, 1000]));
+    }
+
+    #[test]
+    fn percentile_uses_nearest_rank() {
+        let sorted: Vec<u64> = (1..=20).collect();
+        assert_eq!(percentile(&sorted, 50.0), 10);
+        assert_eq!(percentile(&sorted, 95.0), 19);
+        assert_eq!(percentile(&sorted, 100.0), 20);
+        assert_eq!(percentile(&sorted, 0.0), 1);
+        assert_eq!(percentile(&[42], 95.0), 42);
+    }
+
+    #[test]
+    fn summary_of_empty_session_has_no_loss() {
+        let summary = ProbeSession::new(10).summary();
+        assert_eq!((summary.sent, summary.received), (0, 0));
+        assert_eq!(summary.packet_loss, 0.0);
+    }
+
+    #[test]
+    fn summary_reports_partial_loss_and_rtt_stats() {
+        let mut session = ProbeSession::new(10);
+        for rtt in [3000, 1000, 2000, 4000] {
+            session.record(&reply(rtt));
+        }
+        session.record(&Err(EchoError::Timeout));
+
+        let summary = session.summary();
+        assert_eq!((summary.sent, summary.received), (5, 4));
+        assert_eq!(summary.packet_loss, 20.0);
+        assert_eq!((summary.min_rtt_us, summary.avg_rtt_us, summary.max_rtt_us), (1000, 2500, 4000));
+        assert_eq!((summary.p50_rtt_us, summary.p95_rtt_us), (2000, 4000));
+        // 抖动按到达顺序而非排序后的顺序计算
+        assert_eq!(summary.jitter_us, rfc3550_jitter(&[3000, 1000, 2000, 4000]));
+    }
+
+    #[test]
+    fn summary_with_all_probes_lost_is_full_loss() {
+        let mut session = ProbeSession::new(3);
+        session.record(&Err(EchoError::HostUnreachable));
+        session.record(&Err(EchoError::Timeout));
+
+        let summary = session.summary();
+        assert_eq!((summary.sent, summary.received), (2, 0));
+        assert_eq!(summary.packet_loss, 100.0);
+        assert_eq!(summary.max_rtt_us, 0);
+    }
+
+    #[test]
+    fn window_keeps_only_recent_results() {
+        let mut session = ProbeSession::new(3);
+        session.record(&Err(EchoError::Timeout));
+        for rtt in [1000, 2000, 3000] {
+            session.record(&reply(rtt));
+        }
+        let summary = session.summary();
+        assert_eq!((summary.sent, summary.received, summary.min_rtt_us), (3, 3, 1000));
+
+        // 缩小窗口时丢弃最旧的结果
+        session.set_window(2);
+        assert_eq!(session.summary().min_rtt_us, 2000);
+
+        // 窗口至少为 1
+        session.set_window(0);
+        assert_eq!(session.summary().sent, 1);
+
+        session.clear();
+        assert_eq!(session.summary().sent, 0);
+    }
+}
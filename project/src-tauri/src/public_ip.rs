@@ -0,0 +1,1177 @@
+// 公网 IP 和地理位置查询：按设置中的接口顺序依次请求，直到某个接口返回有效结果
+//
+// 旧版为每个接口启动 PowerShell 运行 WebClient，只能在 Windows 上使用，而且会阻塞异步命令。
+// 现在使用 reqwest，TLS 固定用 rustls 和内置根证书，至少 TLS 1.2，
+// 不依赖系统的 TLS 设置和证书库（ISSUES.md 问题 1 中 HTTPS 请求失败的原因）。
+// 每个请求单独超时，一个接口无响应不会拖住整个查询。
+//
+// 每个服务实现 IpProvider：请求地址、响应解析和限流判断各自处理，请求本身由 lookup 统一发出。
+// 自建接口用 custom 类型配置。ProviderHealth 记录每个接口的成功率，被限流的接口在冷却期内跳过。
+// 双栈状态分别用只连接 IPv4 或 IPv6 的客户端查询，得到两个协议各自的公网地址。
+// 配置了离线数据库时，缺少的城市、时区和 ASN 由 geoip 补齐。
+// 接口地址可以在设置中覆盖，指向本地的模拟 HTTP 服务器即可离线测试。
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{tls, Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct IPInfo {
+    pub ip: String,
+    // 天气查询使用的地名，优先为州 / 省
+    pub city: String,
+    pub region: String,
+    pub country: String,
+    // IP 所在地的时区，天气模块据此显示当地时间
+    pub timezone: String,
+    // 自治系统号和运营商，接口和离线数据库都没有时为空
+    pub asn: Option<u32>,
+    pub as_org: String,
+    // 返回结果的接口名称
+    pub provider: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Family {
+    Ipv4,
+    Ipv6,
+}
+
+impl Family {
+    fn matches(self, ip: &str) -> bool {
+        match ip.parse::<IpAddr>() {
+            Ok(IpAddr::V4(_)) => self == Family::Ipv4,
+            Ok(IpAddr::V6(_)) => self == Family::Ipv6,
+            Err(_) => false,
+        }
+    }
+
+    // 绑定到该协议的未指定地址后，连接只会使用该协议的 DNS 结果
+    fn local_address(self) -> IpAddr {
+        match self {
+            Family::Ipv4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            Family::Ipv6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FamilyStatus {
+    pub family: Family,
+    // 是否有接口响应（包括限流和 HTTP 错误），即该协议能否访问公网
+    pub connected: bool,
+    pub ip: Option<String>,
+    pub provider: Option<String>,
+    pub error: Option<String>,
+}
+
+// 日志中最多记录的响应长度
+const MAX_LOGGED_BODY: usize = 200;
+
+// 限流响应没有说明何时恢复时的冷却时间
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomFormat {
+    // JSON 对象，字段名同 ipapi.co：ip（或 query）、city / region、country、timezone、asn、org
+    #[default]
+    Json,
+    // 响应中包含 IP 文本即可（IPv4 或 IPv6）
+    Plain,
+}
+
+// 设置中的一项，列表顺序即尝试顺序；内置接口的 url 为空时使用官方地址
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProviderConfig {
+    IpApiCom {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+    IpapiCo {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+    Ipify {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+    IfconfigMe {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+    IpipNet {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+    // 自建接口
+    Custom {
+        name: String,
+        url: String,
+        #[serde(default)]
+        format: CustomFormat,
+    },
+}
+
+impl ProviderConfig {
+    // 默认顺序：能返回地理位置的接口在前，只返回 IP 的在后
+    pub fn defaults() -> Vec<ProviderConfig> {
+        vec![
+            ProviderConfig::IpApiCom { url: None },
+            ProviderConfig::IpapiCo { url: None },
+            ProviderConfig::Ipify { url: None },
+            ProviderConfig::IfconfigMe { url: None },
+            ProviderConfig::IpipNet { url: None },
+        ]
+    }
+
+    // 只返回 IP、没有地理位置的接口
+    pub fn ip_only(&self) -> bool {
+        matches!(
+            self,
+            ProviderConfig::Ipify { .. }
+                | ProviderConfig::IfconfigMe { .. }
+                | ProviderConfig::Custom { format: CustomFormat::Plain, .. }
+        )
+    }
+
+    pub fn build(&self) -> Box<dyn IpProvider> {
+        let url = |custom: &Option<String>, default: &str| custom.clone().unwrap_or_else(|| default.to_string());
+        match self {
+            ProviderConfig::IpApiCom { url: u } => Box::new(IpApiCom { url: url(u, "http://ip-api.com/json/") }),
+            ProviderConfig::IpapiCo { url: u } => Box::new(IpapiCo { url: url(u, "https://ipapi.co/json/") }),
+            ProviderConfig::Ipify { url: u } => Box::new(Ipify { url: url(u, "https://api64.ipify.org?format=json") }),
+            ProviderConfig::IfconfigMe { url: u } => Box::new(IfconfigMe { url: url(u, "https://ifconfig.me/ip") }),
+            ProviderConfig::IpipNet { url: u } => Box::new(IpipNet { url: url(u, "http://myip.ipip.net") }),
+            ProviderConfig::Custom { name, url, format } => Box::new(Custom {
+                name: name.clone(),
+                url: url.clone(),
+                format: *format,
+            }),
+        }
+    }
+
+    pub fn validate_all(configs: &[ProviderConfig]) -> Result<(), String> {
+        if configs.is_empty() {
+            return Err("至少需要一个公网 IP 接口".to_string());
+        }
+
+        let mut names = HashSet::new();
+        for provider in configs.iter().map(ProviderConfig::build) {
+            if provider.name().trim().is_empty() {
+                return Err("自建公网 IP 接口的名称不能为空".to_string());
+            }
+            if !names.insert(provider.name().to_string()) {
+                return Err(format!("公网 IP 接口重复: {}", provider.name()));
+            }
+            let url = provider.url();
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(format!("公网 IP 接口地址无效: {}", url));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ProviderError {
+    // 连接、TLS、超时等
+    Request(String),
+    Http(StatusCode),
+    RateLimited(Duration),
+    // 响应中没有有效的 IP
+    Invalid(String),
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderError::Request(e) => write!(f, "{}", e),
+            ProviderError::Http(status) => write!(f, "HTTP {}", status),
+            ProviderError::RateLimited(wait) => write!(f, "rate limited for {}s", wait.as_secs()),
+            ProviderError::Invalid(body) => {
+                let logged: String = body.trim().chars().take(MAX_LOGGED_BODY).collect();
+                write!(f, "unexpected response '{}'", logged)
+            }
+        }
+    }
+}
+
+pub trait IpProvider: Send + Sync {
+    // 唯一名称，用于日志和统计
+    fn name(&self) -> &str;
+
+    fn url(&self) -> &str;
+
+    fn parse(&self, body: &str) -> Result<IPInfo, ProviderError>;
+
+    // 服务只有 IPv4 地址（没有 AAAA 记录）时，IPv6 查询跳过该接口
+    fn supports(&self, _family: Family) -> bool {
+        true
+    }
+
+    // 响应表明需要暂停请求时返回冷却时间；默认只处理 429 和 Retry-After
+    fn cooldown(&self, status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+        (status == StatusCode::TOO_MANY_REQUESTS).then(|| retry_after(headers))
+    }
+}
+
+fn header_secs(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+// 只支持秒数形式的 Retry-After
+fn retry_after(headers: &HeaderMap) -> Duration {
+    header_secs(headers, RETRY_AFTER.as_str())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_COOLDOWN)
+}
+
+fn json_value(body: &str) -> Result<serde_json::Value, ProviderError> {
+    serde_json::from_str(body.trim()).map_err(|_| ProviderError::Invalid(body.to_string()))
+}
+
+fn json_str<'a>(data: &'a serde_json::Value, names: &[&str]) -> &'a str {
+    names
+        .iter()
+        .find_map(|name| data.get(*name).and_then(|v| v.as_str()))
+        .unwrap_or("")
+}
+
+// 数字或 "AS15169 Google LLC" 形式，返回 ASN 和后面的名称
+fn parse_asn(value: Option<&serde_json::Value>) -> (Option<u32>, &str) {
+    match value {
+        Some(serde_json::Value::Number(n)) => (n.as_u64().and_then(|n| u32::try_from(n).ok()), ""),
+        Some(serde_json::Value::String(s)) => {
+            let s = s.trim();
+            let rest = s.strip_prefix("AS").or_else(|| s.strip_prefix("as")).unwrap_or(s);
+            let (number, name) = rest.split_once(' ').unwrap_or((rest, ""));
+            (number.parse().ok(), name.trim())
+        }
+        _ => (None, ""),
+    }
+}
+
+// 完整的地址直接解析，否则从文本中查找，IPv4 优先
+fn valid_ip(ip: &str, body: &str) -> Result<String, ProviderError> {
+    match ip.trim().parse::<IpAddr>() {
+        Ok(addr) => Ok(addr.to_string()),
+        Err(_) => extract_ip(ip)
+            .or_else(|| extract_ipv6(ip))
+            .ok_or_else(|| ProviderError::Invalid(body.to_string())),
+    }
+}
+
+fn or_unknown(city: &str) -> String {
+    if city.is_empty() {
+        "Unknown".to_string()
+    } else {
+        city.to_string()
+    }
+}
+
+// 只返回 IP 的接口无法获取城市和时区
+fn ip_only(ip: String) -> IPInfo {
+    IPInfo {
+        ip,
+        city: "Unknown".to_string(),
+        ..Default::default()
+    }
+}
+
+// ip-api.com：免费版无需 API key，限制 45 次/分钟，通过 X-Rl（剩余次数）和 X-Ttl（恢复秒数）告知
+struct IpApiCom {
+    url: String,
+}
+
+impl IpProvider for IpApiCom {
+    fn name(&self) -> &str {
+        "ip-api.com"
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    // {"status":"success","country":"United States","regionName":"Oregon","city":"The Dalles",
+    //  "timezone":"America/Los_Angeles","query":"34.105.5.167"}
+    fn parse(&self, body: &str) -> Result<IPInfo, ProviderError> {
+        let data = json_value(body)?;
+        if json_str(&data, &["status"]) == "fail" {
+            return Err(ProviderError::Invalid(body.to_string()));
+        }
+        // 优先使用州 / 省，美国 IP 显示州名
+        let (asn, as_name) = parse_asn(data.get("as"));
+        Ok(IPInfo {
+            ip: valid_ip(json_str(&data, &["query"]), body)?,
+            city: or_unknown(json_str(&data, &["regionName", "city"])),
+            region: json_str(&data, &["regionName"]).to_string(),
+            country: json_str(&data, &["country"]).to_string(),
+            timezone: json_str(&data, &["timezone"]).to_string(),
+            asn,
+            as_org: if as_name.is_empty() { json_str(&data, &["org", "isp"]) } else { as_name }.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn supports(&self, family: Family) -> bool {
+        family == Family::Ipv4
+    }
+
+    fn cooldown(&self, status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+        let ttl = header_secs(headers, "X-Ttl").map(Duration::from_secs);
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Some(ttl.unwrap_or_else(|| retry_after(headers)));
+        }
+        // 本次响应仍然有效，但在额度恢复前不再请求
+        match header_secs(headers, "X-Rl") {
+            Some(0) => ttl,
+            _ => None,
+        }
+    }
+}
+
+// ipapi.co：免费版每天 1000 次，超出时返回 429 或 {"error": true, "reason": "RateLimited"}
+struct IpapiCo {
+    url: String,
+}
+
+impl IpProvider for IpapiCo {
+    fn name(&self) -> &str {
+        "ipapi.co"
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    // country 是两位代码，country_name 是全称
+    fn parse(&self, body: &str) -> Result<IPInfo, ProviderError> {
+        let data = json_value(body)?;
+        if data.get("error").and_then(|v| v.as_bool()) == Some(true) {
+            return match json_str(&data, &["reason"]) {
+                "RateLimited" => Err(ProviderError::RateLimited(DEFAULT_COOLDOWN)),
+                _ => Err(ProviderError::Invalid(body.to_string())),
+            };
+        }
+        Ok(IPInfo {
+            ip: valid_ip(json_str(&data, &["ip"]), body)?,
+            city: or_unknown(json_str(&data, &["region", "city"])),
+            region: json_str(&data, &["region"]).to_string(),
+            country: json_str(&data, &["country_name", "country"]).to_string(),
+            timezone: json_str(&data, &["timezone"]).to_string(),
+            asn: parse_asn(data.get("asn")).0,
+            as_org: json_str(&data, &["org"]).to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+// ipify：{"ip": "..."}，只有 IP；api64 同时有 IPv4 和 IPv6 地址
+struct Ipify {
+    url: String,
+}
+
+impl IpProvider for Ipify {
+    fn name(&self) -> &str {
+        "ipify"
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    // 也兼容不带 ?format=json 时的纯文本响应
+    fn parse(&self, body: &str) -> Result<IPInfo, ProviderError> {
+        let ip = match json_value(body) {
+            Ok(data) => valid_ip(json_str(&data, &["ip"]), body)?,
+            Err(_) => valid_ip(body, body)?,
+        };
+        Ok(ip_only(ip))
+    }
+}
+
+// ifconfig.me/ip：纯文本 IP
+struct IfconfigMe {
+    url: String,
+}
+
+impl IpProvider for IfconfigMe {
+    fn name(&self) -> &str {
+        "ifconfig.me"
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn parse(&self, body: &str) -> Result<IPInfo, ProviderError> {
+        Ok(ip_only(valid_ip(body, body)?))
+    }
+}
+
+// myip.ipip.net："当前 IP：1.2.3.4  来自于：中国 上海 上海  电信"，只支持 IPv4
+struct IpipNet {
+    url: String,
+}
+
+impl IpProvider for IpipNet {
+    fn name(&self) -> &str {
+        "myip.ipip.net"
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn supports(&self, family: Family) -> bool {
+        family == Family::Ipv4
+    }
+
+    fn parse(&self, body: &str) -> Result<IPInfo, ProviderError> {
+        Ok(IPInfo {
+            ip: valid_ip(body, body)?,
+            city: extract_city(body).unwrap_or("Unknown").to_string(),
+            country: if body.contains("中国") { "China".to_string() } else { String::new() },
+            ..Default::default()
+        })
+    }
+}
+
+struct Custom {
+    name: String,
+    url: String,
+    format: CustomFormat,
+}
+
+impl IpProvider for Custom {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn parse(&self, body: &str) -> Result<IPInfo, ProviderError> {
+        match self.format {
+            CustomFormat::Json => {
+                let data = json_value(body)?;
+                let (asn, as_name) = parse_asn(data.get("asn"));
+                Ok(IPInfo {
+                    ip: valid_ip(json_str(&data, &["ip", "query"]), body)?,
+                    city: or_unknown(json_str(&data, &["city", "region", "regionName"])),
+                    region: json_str(&data, &["region", "regionName"]).to_string(),
+                    country: json_str(&data, &["country_name", "country"]).to_string(),
+                    timezone: json_str(&data, &["timezone"]).to_string(),
+                    asn,
+                    as_org: if as_name.is_empty() { json_str(&data, &["as_org", "org"]) } else { as_name }.to_string(),
+                    ..Default::default()
+                })
+            }
+            CustomFormat::Plain => Ok(ip_only(valid_ip(body, body)?)),
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ProviderStats {
+    pub attempts: u32,
+    pub successes: u32,
+    pub rate_limited: u32,
+    pub last_error: Option<String>,
+    // Unix 毫秒
+    pub last_success: Option<u64>,
+    // 冷却期结束前跳过该接口，Unix 毫秒
+    pub skip_until: Option<u64>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ProviderStatus {
+    pub name: String,
+    pub url: String,
+    // 0-1，还没有请求过时为 None
+    pub success_rate: Option<f64>,
+    #[serde(flatten)]
+    pub stats: ProviderStats,
+}
+
+// 各接口的请求统计，按名称记录，只保存在内存中
+#[derive(Default)]
+pub struct ProviderHealth {
+    stats: Mutex<HashMap<String, ProviderStats>>,
+}
+
+impl ProviderHealth {
+    fn with<R>(&self, f: impl FnOnce(&mut HashMap<String, ProviderStats>) -> R) -> R {
+        match self.stats.lock() {
+            Ok(mut stats) => f(&mut stats),
+            Err(poisoned) => f(&mut poisoned.into_inner()),
+        }
+    }
+
+    fn skip_until(&self, name: &str, now: u64) -> Option<u64> {
+        self.with(|stats| stats.get(name)?.skip_until.filter(|&until| until > now))
+    }
+
+    fn record(&self, name: &str, result: &Result<IPInfo, ProviderError>, cooldown: Option<Duration>, now: u64) {
+        self.with(|stats| {
+            let entry = stats.entry(name.to_string()).or_default();
+            entry.attempts += 1;
+            entry.skip_until = cooldown.map(|wait| now + wait.as_millis() as u64);
+            match result {
+                Ok(_) => {
+                    entry.successes += 1;
+                    entry.last_success = Some(now);
+                }
+                Err(e) => {
+                    if matches!(e, ProviderError::RateLimited(_)) {
+                        entry.rate_limited += 1;
+                    }
+                    entry.last_error = Some(e.to_string());
+                }
+            }
+        });
+    }
+
+    pub fn status(&self, configs: &[ProviderConfig]) -> Vec<ProviderStatus> {
+        self.with(|stats| {
+            configs
+                .iter()
+                .map(ProviderConfig::build)
+                .map(|provider| {
+                    let stats = stats.get(provider.name()).cloned().unwrap_or_default();
+                    ProviderStatus {
+                        name: provider.name().to_string(),
+                        url: provider.url().to_string(),
+                        success_rate: (stats.attempts > 0)
+                            .then(|| stats.successes as f64 / stats.attempts as f64),
+                        stats,
+                    }
+                })
+                .collect()
+        })
+    }
+}
+
+// family 为 None 时两种协议都可以使用，由系统决定
+pub fn client(timeout: Duration, family: Option<Family>) -> Result<Client, String> {
+    Client::builder()
+        .local_address(family.map(Family::local_address))
+        .use_rustls_tls()
+        .min_tls_version(tls::Version::TLS_1_2)
+        .connect_timeout(timeout)
+        .timeout(timeout)
+        .user_agent(concat!("floating-stats/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))
+}
+
+// reqwest 的错误信息只有一句 "error sending request"，附上底层原因（DNS、TLS、连接被拒绝等）
+fn describe(error: reqwest::Error) -> String {
+    if error.is_timeout() {
+        return "timed out".to_string();
+    }
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(&error);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
+
+// 返回查询结果和之后需要暂停请求的时间
+async fn request(client: &Client, provider: &dyn IpProvider) -> (Result<IPInfo, ProviderError>, Option<Duration>) {
+    let response = match client.get(provider.url()).send().await {
+        Ok(response) => response,
+        Err(e) => return (Err(ProviderError::Request(describe(e))), None),
+    };
+
+    let status = response.status();
+    let cooldown = provider.cooldown(status, response.headers());
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return (Err(ProviderError::RateLimited(cooldown.unwrap_or(DEFAULT_COOLDOWN))), cooldown);
+    }
+    if !status.is_success() {
+        return (Err(ProviderError::Http(status)), cooldown);
+    }
+
+    let result = match response.text().await {
+        Ok(body) => provider.parse(&body),
+        Err(e) => Err(ProviderError::Request(describe(e))),
+    };
+    // 响应内容表明被限流时同样暂停
+    let cooldown = match &result {
+        Err(ProviderError::RateLimited(wait)) => Some(cooldown.map_or(*wait, |c| c.max(*wait))),
+        _ => cooldown,
+    };
+    (result, cooldown)
+}
+
+// 按顺序尝试各接口，跳过冷却中的接口和不支持该协议的接口
+// 返回查询结果和是否有接口响应过；指定协议时没有任何响应多半是本机没有该协议的连通性
+pub async fn try_providers(
+    client: &Client,
+    configs: &[ProviderConfig],
+    health: &ProviderHealth,
+    family: Option<Family>,
+) -> (Result<IPInfo, String>, bool) {
+    let mut responded = false;
+    for provider in configs.iter().map(ProviderConfig::build) {
+        let name = provider.name();
+        if family.is_some_and(|family| !provider.supports(family)) {
+            continue;
+        }
+        if let Some(until) = health.skip_until(name, now_ms()) {
+            debug!("Skipping IP provider {} until {}", name, until);
+            continue;
+        }
+
+        debug!("Trying IP provider {} ({}) over {:?}", name, provider.url(), family);
+        let (mut result, cooldown) = request(client, provider.as_ref()).await;
+        // NAT64 网络中 IPv6 连接可能经 DNS64 到达只有 IPv4 的服务，返回的是 IPv4 出口地址
+        if let (Some(family), Ok(info)) = (family, &result) {
+            if !family.matches(&info.ip) {
+                result = Err(ProviderError::Invalid(format!("{} (expected {:?})", info.ip, family)));
+            }
+        }
+        let unreachable = matches!(result, Err(ProviderError::Request(_)));
+        responded |= !unreachable;
+        // 指定协议时连接失败多半是本机没有该协议的连通性，不计入接口的成功率
+        if family.is_none() || !unreachable {
+            health.record(name, &result, cooldown, now_ms());
+        }
+
+        match result {
+            Ok(mut info) => {
+                info!(
+                    "Got IP {} from {} - city: {}, country: {}, timezone: {}",
+                    info.ip, name, info.city, info.country, info.timezone
+                );
+                info.provider = name.to_string();
+                return (Ok(info), true);
+            }
+            Err(e) => warn!("IP provider {} failed: {}", name, e),
+        }
+    }
+
+    (Err("所有IP API都失败了".to_string()), responded)
+}
+
+// 指定 family 时 client 需用 client(timeout, Some(family)) 创建
+pub async fn lookup(
+    client: &Client,
+    configs: &[ProviderConfig],
+    health: &ProviderHealth,
+    family: Option<Family>,
+) -> Result<IPInfo, String> {
+    try_providers(client, configs, health, family).await.0
+}
+
+pub async fn lookup_family(
+    client: &Client,
+    configs: &[ProviderConfig],
+    health: &ProviderHealth,
+    family: Family,
+) -> FamilyStatus {
+    let (result, connected) = try_providers(client, configs, health, Some(family)).await;
+    match result {
+        Ok(info) => FamilyStatus {
+            family,
+            connected,
+            ip: Some(info.ip),
+            provider: Some(info.provider),
+            error: None,
+        },
+        Err(e) => FamilyStatus {
+            family,
+            connected,
+            ip: None,
+            provider: None,
+            error: Some(e),
+        },
+    }
+}
+
+// 从任意文本中找出第一个 IPv4 地址，逐字符扫描，不受中文等前后内容影响
+pub fn extract_ip(text: &str) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        // Try to find an IP starting at position i
+        let mut octet_start = i;
+        let mut octets = Vec::new();
+        let mut valid = true;
+
+        for octet_num in 0..4 {
+            // Find start of octet (digit)
+            while octet_start < chars.len() && !chars[octet_start].is_ascii_digit() {
+                octet_start += 1;
+            }
+
+            if octet_start >= chars.len() {
+                valid = false;
+                break;
+            }
+
+            // Find end of octet
+            let mut octet_end = octet_start;
+            while octet_end < chars.len() && chars[octet_end].is_ascii_digit() {
+                octet_end += 1;
+            }
+
+            let octet_str: String = chars[octet_start..octet_end].iter().collect();
+            let octet_val: u32 = octet_str.parse().unwrap_or(256);
+
+            if octet_val > 255 {
+                valid = false;
+                break;
+            }
+
+            octets.push(octet_str);
+
+            // Check for dot between octets (except after last octet)
+            if octet_num < 3 {
+                if octet_end >= chars.len() || chars[octet_end] != '.' {
+                    valid = false;
+                    break;
+                }
+                octet_start = octet_end + 1;
+            } else if octet_end < chars.len() && chars[octet_end].is_ascii_digit() {
+                // After 4th octet, should not be followed by digit
+                valid = false;
+            }
+        }
+
+        if valid && octets.len() == 4 {
+            return Some(format!("{}.{}.{}.{}", octets[0], octets[1], octets[2], octets[3]));
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+// 从任意文本中找出第一个 IPv6 地址：按地址中不会出现的字符切分，候选片段交给标准库解析
+// 不接受未指定地址 "::"；"12:30" 这样的时间不是合法地址，不会被误认
+pub fn extract_ipv6(text: &str) -> Option<String> {
+    text.split(|c: char| !(c.is_ascii_hexdigit() || c == ':' || c == '.'))
+        .filter(|token| token.contains(':'))
+        .find_map(|token| {
+            // 句末的句号，或 "addr:2001:db8::1" 切分后残留的前导冒号
+            [token.trim_end_matches('.'), token.trim_matches(|c| c == ':' || c == '.')]
+                .into_iter()
+                .find_map(|candidate| candidate.parse::<Ipv6Addr>().ok())
+                .filter(|ip| !ip.is_unspecified())
+        })
+        .map(|ip| ip.to_string())
+}
+
+// 中文响应中的城市名
+fn extract_city(text: &str) -> Option<&'static str> {
+    let cities = [
+        ("上海", "Shanghai"),
+        ("北京", "Beijing"),
+        ("广州", "Guangzhou"),
+        ("深圳", "Shenzhen"),
+        ("天津", "Tianjin"),
+        ("杭州", "Hangzhou"),
+        ("成都", "Chengdu"),
+        ("重庆", "Chongqing"),
+        ("武汉", "Wuhan"),
+        ("西安", "Xian"),
+    ];
+
+    cities
+        .iter()
+        .find(|(chinese, _)| text.contains(chinese))
+        .map(|(_, english)| *english)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn parse(config: ProviderConfig, body: &str) -> Result<IPInfo, ProviderError> {
+        config.build().parse(body)
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn parses_ip_api_com_json() {
+        let body = r#"{"status":"success","country":"United States","regionName":"Oregon","city":"The Dalles",
+            "timezone":"America/Los_Angeles","as":"AS15169 Google LLC","org":"Google Cloud","query":"34.105.5.167"}"#;
+        let info = parse(ProviderConfig::IpApiCom { url: None }, body).unwrap();
+
+        assert_eq!(info.ip, "34.105.5.167");
+        // 优先显示州名
+        assert_eq!(info.city, "Oregon");
+        assert_eq!(info.country, "United States");
+        assert_eq!(info.timezone, "America/Los_Angeles");
+        assert_eq!(info.asn, Some(15169));
+        assert_eq!(info.as_org, "Google LLC");
+
+        let failed = r#"{"status":"fail","message":"reserved range","query":"127.0.0.1"}"#;
+        assert!(matches!(parse(ProviderConfig::IpApiCom { url: None }, failed), Err(ProviderError::Invalid(_))));
+    }
+
+    #[test]
+    fn parses_ipapi_co_json_and_rate_limit_body() {
+        let body = r#"{"ip":"2001:db8::1","city":"Berlin","region":"Land Berlin","country":"DE",
+            "country_name":"Germany","timezone":"Europe/Berlin","asn":"AS3320","org":"Deutsche Telekom AG"}"#;
+        let info = parse(ProviderConfig::IpapiCo { url: None }, body).unwrap();
+
+        assert_eq!(info.ip, "2001:db8::1");
+        assert_eq!(info.city, "Land Berlin");
+        assert_eq!(info.country, "Germany");
+        assert_eq!(info.asn, Some(3320));
+        assert_eq!(info.as_org, "Deutsche Telekom AG");
+
+        let limited = r#"{"error": true, "reason": "RateLimited", "message": "Visit https://ipapi.co/ratelimited/"}"#;
+        assert!(matches!(
+            parse(ProviderConfig::IpapiCo { url: None }, limited),
+            Err(ProviderError::RateLimited(wait)) if wait == DEFAULT_COOLDOWN
+        ));
+    }
+
+    #[test]
+    fn parses_plain_text_providers() {
+        // ipify 兼容 JSON 和纯文本
+        let json = parse(ProviderConfig::Ipify { url: None }, r#"{"ip":"203.0.113.7"}"#).unwrap();
+        assert_eq!((json.ip.as_str(), json.city.as_str()), ("203.0.113.7", "Unknown"));
+        let plain = parse(ProviderConfig::Ipify { url: None }, "203.0.113.7\n").unwrap();
+        assert_eq!(plain.ip, "203.0.113.7");
+
+        let ifconfig = parse(ProviderConfig::IfconfigMe { url: None }, "2001:db8:0:0::42").unwrap();
+        assert_eq!(ifconfig.ip, "2001:db8::42");
+
+        assert!(matches!(
+            parse(ProviderConfig::IfconfigMe { url: None }, "<html>error</html>"),
+            Err(ProviderError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn parses_ipip_net_text() {
+        let info = parse(ProviderConfig::IpipNet { url: None }, "当前 IP：101.86.1.2  来自于：中国 上海 上海  电信\n")
+            .unwrap();
+        assert_eq!(info.ip, "101.86.1.2");
+        assert_eq!(info.city, "Shanghai");
+        assert_eq!(info.country, "China");
+
+        let abroad = parse(ProviderConfig::IpipNet { url: None }, "当前 IP：198.51.100.9  来自于：美国").unwrap();
+        assert_eq!((abroad.city.as_str(), abroad.country.as_str()), ("Unknown", ""));
+    }
+
+    #[test]
+    fn parses_custom_formats() {
+        let json = ProviderConfig::Custom {
+            name: "self-hosted".to_string(),
+            url: "https://ip.example.com".to_string(),
+            format: CustomFormat::Json,
+        };
+        let info = parse(json, r#"{"query":"192.0.2.10","regionName":"Bavaria","asn":64500,"as_org":"Example"}"#)
+            .unwrap();
+        assert_eq!(info.ip, "192.0.2.10");
+        assert_eq!(info.city, "Bavaria");
+        assert_eq!(info.asn, Some(64500));
+        assert_eq!(info.as_org, "Example");
+
+        let plain = ProviderConfig::Custom {
+            name: "self-hosted".to_string(),
+            url: "https://ip.example.com".to_string(),
+            format: CustomFormat::Plain,
+        };
+        assert_eq!(parse(plain, "your address is 192.0.2.11.").unwrap().ip, "192.0.2.11");
+    }
+
+    #[test]
+    fn ip_only_providers() {
+        let only: Vec<bool> = ProviderConfig::defaults().iter().map(ProviderConfig::ip_only).collect();
+        assert_eq!(only, [false, false, true, true, false]);
+
+        let custom = |format| ProviderConfig::Custom { name: "x".to_string(), url: "https://x".to_string(), format };
+        assert!(custom(CustomFormat::Plain).ip_only());
+        assert!(!custom(CustomFormat::Json).ip_only());
+    }
+
+    #[test]
+    fn default_cooldown_uses_retry_after_on_429_only() {
+        let provider = ProviderConfig::Ipify { url: None }.build();
+        let limited = headers(&[("retry-after", "120")]);
+
+        assert_eq!(provider.cooldown(StatusCode::TOO_MANY_REQUESTS, &limited), Some(Duration::from_secs(120)));
+        assert_eq!(provider.cooldown(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new()), Some(DEFAULT_COOLDOWN));
+        // HTTP 日期形式的 Retry-After 不支持，按默认冷却时间处理
+        let date = headers(&[("retry-after", "Wed, 21 Oct 2026 07:28:00 GMT")]);
+        assert_eq!(provider.cooldown(StatusCode::TOO_MANY_REQUESTS, &date), Some(DEFAULT_COOLDOWN));
+        assert_eq!(provider.cooldown(StatusCode::OK, &limited), None);
+    }
+
+    #[test]
+    fn ip_api_com_cooldown_follows_rate_limit_headers() {
+        let provider = ProviderConfig::IpApiCom { url: None }.build();
+
+        // 额度用完时本次响应仍然有效，但在 X-Ttl 秒内不再请求
+        let exhausted = headers(&[("x-rl", "0"), ("x-ttl", "42")]);
+        assert_eq!(provider.cooldown(StatusCode::OK, &exhausted), Some(Duration::from_secs(42)));
+        assert_eq!(provider.cooldown(StatusCode::OK, &headers(&[("x-rl", "3"), ("x-ttl", "42")])), None);
+
+        // 429 时 X-Ttl 优先于 Retry-After
+        let limited = headers(&[("x-ttl", "15"), ("retry-after", "120")]);
+        assert_eq!(provider.cooldown(StatusCode::TOO_MANY_REQUESTS, &limited), Some(Duration::from_secs(15)));
+        let retry = headers(&[("retry-after", "120")]);
+        assert_eq!(provider.cooldown(StatusCode::TOO_MANY_REQUESTS, &retry), Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn extract_ipv6_finds_address_in_text() {
+        assert_eq!(extract_ipv6("2001:db8::1"), Some("2001:db8::1".to_string()));
+        assert_eq!(extract_ipv6("当前 IP：2001:DB8:0:0:0:0:0:2 来自于：德国"), Some("2001:db8::2".to_string()));
+        assert_eq!(extract_ipv6("Your IP is 2001:db8::3."), Some("2001:db8::3".to_string()));
+        // 前导冒号是切分残留
+        assert_eq!(extract_ipv6("addr:2001:db8::4/64"), Some("2001:db8::4".to_string()));
+        // 内嵌 IPv4 的地址
+        assert_eq!(extract_ipv6("::ffff:192.0.2.1"), Some("::ffff:192.0.2.1".to_string()));
+        // 返回第一个有效地址
+        assert_eq!(extract_ipv6("fe80::1%eth0, 2001:db8::5"), Some("fe80::1".to_string()));
+    }
+
+    #[test]
+    fn extract_ipv6_ignores_non_addresses() {
+        assert_eq!(extract_ipv6("updated at 12:30:45"), None);
+        assert_eq!(extract_ipv6("::"), None);
+        assert_eq!(extract_ipv6("203.0.113.7"), None);
+        assert_eq!(extract_ipv6("MAC 00:11:22:aa:bb:cc"), None);
+        assert_eq!(extract_ipv6(""), None);
+    }
+
+    #[test]
+    fn extract_ip_finds_ipv4_in_text() {
+        assert_eq!(extract_ip("当前 IP：1.2.3.4  来自于：中国"), Some("1.2.3.4".to_string()));
+        assert_eq!(extract_ip("version 1.2 then 10.0.0.1"), Some("10.0.0.1".to_string()));
+        assert_eq!(extract_ip("2001:db8::1"), None);
+    }
+
+    // valid_ip 先整体解析，IPv4 优先于文本中的 IPv6
+    #[test]
+    fn valid_ip_prefers_ipv4_in_text() {
+        assert_eq!(valid_ip(" 2001:db8::1 ", "").unwrap(), "2001:db8::1");
+        assert_eq!(valid_ip("v6 2001:db8::1 v4 192.0.2.1", "").unwrap(), "192.0.2.1");
+        assert!(matches!(valid_ip("none", "none"), Err(ProviderError::Invalid(_))));
+    }
+
+    // 本机的模拟 HTTP 服务器：按路径返回预设的响应，并记录收到的请求路径
+    struct MockServer {
+        base: String,
+        requests: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MockServer {
+        fn start(routes: Vec<(&str, String)>) -> Self {
+            let routes: HashMap<String, String> =
+                routes.into_iter().map(|(path, raw)| (path.to_string(), raw)).collect();
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let base = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Arc::new(Mutex::new(Vec::new()));
+
+            let log = Arc::clone(&requests);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(mut stream) = stream else {
+                        continue;
+                    };
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut request_line = String::new();
+                    let _ = reader.read_line(&mut request_line);
+                    // 跳过请求头，直到空行
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) <= 2 {
+                            break;
+                        }
+                    }
+
+                    let path = request_line.split_whitespace().nth(1).unwrap_or("").to_string();
+                    let raw = routes.get(&path).cloned().unwrap_or_else(|| reply("404 Not Found", &[], ""));
+                    log.lock().unwrap().push(path);
+                    let _ = stream.write_all(raw.as_bytes());
+                }
+            });
+
+            MockServer { base, requests }
+        }
+
+        fn url(&self, path: &str) -> Option<String> {
+            Some(format!("{}{}", self.base, path))
+        }
+
+        fn hits(&self, path: &str) -> usize {
+            self.requests.lock().unwrap().iter().filter(|p| *p == path).count()
+        }
+    }
+
+    fn reply(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+        let mut raw = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+        for (name, value) in headers {
+            raw.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        raw + "\r\n" + body
+    }
+
+    fn test_client() -> Client {
+        client(Duration::from_secs(5), None).unwrap()
+    }
+
+    fn stats(health: &ProviderHealth, configs: &[ProviderConfig], name: &str) -> ProviderStats {
+        health.status(configs).into_iter().find(|s| s.name == name).unwrap().stats
+    }
+
+    #[tokio::test]
+    async fn rate_limited_provider_falls_back_and_cools_down() {
+        let server = MockServer::start(vec![
+            ("/ipapi", reply("429 Too Many Requests", &[("Retry-After", "120")], "")),
+            ("/ipify", reply("200 OK", &[], r#"{"ip":"203.0.113.7"}"#)),
+        ]);
+        let configs = [
+            ProviderConfig::IpapiCo { url: server.url("/ipapi") },
+            ProviderConfig::Ipify { url: server.url("/ipify") },
+        ];
+        let health = ProviderHealth::default();
+
+        let before = now_ms();
+        let info = lookup(&test_client(), &configs, &health, None).await.unwrap();
+        let after = now_ms();
+        assert_eq!(info.ip, "203.0.113.7");
+        assert_eq!(info.provider, "ipify");
+
+        let limited = stats(&health, &configs, "ipapi.co");
+        assert_eq!((limited.attempts, limited.rate_limited, limited.successes), (1, 1, 0));
+        let until = limited.skip_until.unwrap();
+        assert!(until >= before + 120_000 && until <= after + 120_000);
+
+        // 冷却期内不再请求被限流的接口
+        lookup(&test_client(), &configs, &health, None).await.unwrap();
+        assert_eq!(server.hits("/ipapi"), 1);
+        assert_eq!(server.hits("/ipify"), 2);
+    }
+
+    #[tokio::test]
+    async fn exhausted_quota_keeps_response_and_skips_provider() {
+        let body = r#"{"status":"success","regionName":"Oregon","country":"United States","query":"34.105.5.167"}"#;
+        let server = MockServer::start(vec![
+            ("/ip-api", reply("200 OK", &[("X-Rl", "0"), ("X-Ttl", "30")], body)),
+            ("/ipify", reply("200 OK", &[], r#"{"ip":"34.105.5.167"}"#)),
+        ]);
+        let configs = [
+            ProviderConfig::IpApiCom { url: server.url("/ip-api") },
+            ProviderConfig::Ipify { url: server.url("/ipify") },
+        ];
+        let health = ProviderHealth::default();
+
+        let before = now_ms();
+        let info = lookup(&test_client(), &configs, &health, None).await.unwrap();
+        assert_eq!((info.provider.as_str(), info.city.as_str()), ("ip-api.com", "Oregon"));
+
+        let ip_api = stats(&health, &configs, "ip-api.com");
+        assert_eq!((ip_api.successes, ip_api.rate_limited), (1, 0));
+        assert!(ip_api.skip_until.unwrap() >= before + 30_000);
+
+        // 额度恢复前改用下一个接口
+        let info = lookup(&test_client(), &configs, &health, None).await.unwrap();
+        assert_eq!(info.provider, "ipify");
+        assert_eq!(server.hits("/ip-api"), 1);
+    }
+
+    #[tokio::test]
+    async fn falls_back_through_http_errors_and_invalid_bodies() {
+        let server = MockServer::start(vec![
+            ("/error", reply("500 Internal Server Error", &[], "")),
+            ("/garbage", reply("200 OK", &[], "<html>maintenance</html>")),
+            ("/plain", reply("200 OK", &[], "198.51.100.20\n")),
+        ]);
+        let custom = |name: &str, path: &str| ProviderConfig::Custom {
+            name: name.to_string(),
+            url: server.url(path).unwrap(),
+            format: CustomFormat::Plain,
+        };
+        let configs = [custom("error", "/error"), custom("garbage", "/garbage"), custom("plain", "/plain")];
+        let health = ProviderHealth::default();
+
+        let info = lookup(&test_client(), &configs, &health, None).await.unwrap();
+        assert_eq!((info.ip.as_str(), info.provider.as_str()), ("198.51.100.20", "plain"));
+
+        let error = stats(&health, &configs, "error");
+        assert_eq!(error.last_error.as_deref(), Some("HTTP 500 Internal Server Error"));
+        assert_eq!(error.skip_until, None);
+        assert!(stats(&health, &configs, "garbage").last_error.unwrap().starts_with("unexpected response"));
+
+        let statuses = health.status(&configs);
+        let rates: Vec<Option<f64>> = statuses.iter().map(|s| s.success_rate).collect();
+        assert_eq!(rates, [Some(0.0), Some(0.0), Some(1.0)]);
+    }
+
+    #[tokio::test]
+    async fn family_lookup_reports_connectivity() {
+        let server = MockServer::start(vec![("/error", reply("503 Service Unavailable", &[], ""))]);
+        // 绑定后立即关闭，连接会被拒绝
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let client = client(Duration::from_secs(5), Some(Family::Ipv4)).unwrap();
+        let health = ProviderHealth::default();
+
+        let unreachable = [ProviderConfig::IfconfigMe { url: Some(format!("http://{}/ip", closed)) }];
+        let status = lookup_family(&client, &unreachable, &health, Family::Ipv4).await;
+        assert!(!status.connected);
+        assert_eq!(status.ip, None);
+        // 指定协议时连接失败不计入接口的成功率
+        assert_eq!(stats(&health, &unreachable, "ifconfig.me").attempts, 0);
+
+        // 接口返回错误说明该协议可以访问公网
+        let failing = [ProviderConfig::IfconfigMe { url: server.url("/error") }];
+        let status = lookup_family(&client, &failing, &health, Family::Ipv4).await;
+        assert!(status.connected);
+        assert_eq!(status.error.as_deref(), Some("所有IP API都失败了"));
+    }
+
+    #[tokio::test]
+    async fn family_lookup_rejects_address_of_other_family() {
+        let server = MockServer::start(vec![("/ip", reply("200 OK", &[], "2001:db8::7"))]);
+        let client = client(Duration::from_secs(5), Some(Family::Ipv4)).unwrap();
+        let configs = [ProviderConfig::IfconfigMe { url: server.url("/ip") }];
+
+        // 经 NAT64 等返回的另一协议地址不作为该协议的结果
+        let status = lookup_family(&client, &configs, &ProviderHealth::default(), Family::Ipv4).await;
+        assert!(status.connected);
+        assert_eq!(status.ip, None);
+    }
+}
@@ -0,0 +1,374 @@
+// 连接质量评级：按评分标准把延迟、抖动、丢包率分为 良好 / 一般 / 较差，并给出 0-100 分
+//
+// 评分标准可以选预设（游戏、视频通话、网页浏览）、自定义阈值，或按 E-model 估算的 MOS 分级。
+// 后台只给出 ConnectionQuality 的稳定标识（good / fair / poor / unknown），前端按标识选择样式和显示的文字。
+
+use crate::settings::ConfigFile;
+use serde::{Deserialize, Serialize};
+
+pub const CONFIG_FILE_NAME: &str = "quality.json";
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionQuality {
+    // 还没有探测结果
+    #[default]
+    Unknown,
+    Good,
+    Fair,
+    Poor,
+}
+
+// 超过任意一项即降到对应等级；延迟和抖动为 ms，丢包率为 %
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Thresholds {
+    pub latency: f64,
+    pub jitter: f64,
+    pub packet_loss: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ScoringProfile {
+    pub fair: Thresholds,
+    pub poor: Thresholds,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(tag = "preset", rename_all = "snake_case")]
+pub enum QualityConfig {
+    Gaming,
+    VideoCall,
+    // 延迟和丢包阈值与旧版固定阈值一致（50 / 100 ms，2% / 5%）；
+    // 旧版不看抖动，这里另加宽松的抖动阈值 30 / 50 ms，抖动很大时会比旧版低一级
+    #[default]
+    Browsing,
+    Custom {
+        fair: Thresholds,
+        poor: Thresholds,
+    },
+    // 按语音通话 MOS 分级：低于 fair 为一般，低于 poor 为较差；分数为 R 因子
+    Mos {
+        #[serde(default = "default_mos_fair")]
+        fair: f64,
+        #[serde(default = "default_mos_poor")]
+        poor: f64,
+    },
+}
+
+// G.107 附录 B：MOS 4.03 以上用户满意，3.6 以下部分用户不满意
+fn default_mos_fair() -> f64 {
+    4.03
+}
+
+fn default_mos_poor() -> f64 {
+    3.6
+}
+
+impl QualityConfig {
+    // 按 MOS 分级时没有阈值表
+    pub fn profile(&self) -> Option<ScoringProfile> {
+        let (fair, poor) = match *self {
+            QualityConfig::Gaming => (
+                Thresholds { latency: 30.0, jitter: 5.0, packet_loss: 0.5 },
+                Thresholds { latency: 60.0, jitter: 15.0, packet_loss: 2.0 },
+            ),
+            // ITU-T G.114 建议单向延迟不超过 150 ms
+            QualityConfig::VideoCall => (
+                Thresholds { latency: 100.0, jitter: 20.0, packet_loss: 1.0 },
+                Thresholds { latency: 200.0, jitter: 40.0, packet_loss: 3.0 },
+            ),
+            QualityConfig::Browsing => (
+                Thresholds { latency: 50.0, jitter: 30.0, packet_loss: 2.0 },
+                Thresholds { latency: 100.0, jitter: 50.0, packet_loss: 5.0 },
+            ),
+            QualityConfig::Custom { fair, poor } => (fair, poor),
+            QualityConfig::Mos { .. } => return None,
+        };
+        Some(ScoringProfile { fair, poor })
+    }
+
+    // 返回 (质量等级, 0-100 分)；latency 为 RTT，单位与 Thresholds 相同
+    pub fn assess(&self, latency: f64, jitter: f64, packet_loss: f64) -> (ConnectionQuality, f64) {
+        if let QualityConfig::Mos { fair, poor } = *self {
+            let r = r_factor(latency, jitter, packet_loss);
+            let quality = match mos(r) {
+                m if m >= fair => ConnectionQuality::Good,
+                m if m >= poor => ConnectionQuality::Fair,
+                _ => ConnectionQuality::Poor,
+            };
+            return (quality, r.clamp(0.0, 100.0));
+        }
+
+        match self.profile() {
+            Some(profile) => (
+                profile.classify(latency, jitter, packet_loss),
+                profile.score(latency, jitter, packet_loss),
+            ),
+            None => (ConnectionQuality::Unknown, 0.0),
+        }
+    }
+}
+
+impl ConfigFile for QualityConfig {
+    const FILE_NAME: &'static str = CONFIG_FILE_NAME;
+    const LABEL: &'static str = "评分标准";
+
+    fn validate(&self) -> Result<(), String> {
+        if let QualityConfig::Mos { fair, poor } = *self {
+            return if (1.0..=4.5).contains(&poor) && (1.0..=4.5).contains(&fair) && poor < fair {
+                Ok(())
+            } else {
+                Err("MOS 阈值必须在 1-4.5 之间，且 \"一般\" 阈值大于 \"较差\" 阈值".to_string())
+            };
+        }
+
+        let Some(ScoringProfile { fair, poor }) = self.profile() else {
+            return Ok(());
+        };
+        let pairs = [
+            (fair.latency, poor.latency),
+            (fair.jitter, poor.jitter),
+            (fair.packet_loss, poor.packet_loss),
+        ];
+        if pairs.iter().all(|&(f, p)| f.is_finite() && p.is_finite() && 0.0 < f && f < p) {
+            Ok(())
+        } else {
+            Err("评分阈值必须为正数，且 \"一般\" 阈值小于 \"较差\" 阈值".to_string())
+        }
+    }
+}
+
+// 单项得分：0 为 100 分，"一般" 阈值为 70 分，"较差" 阈值为 40 分，两倍 "较差" 阈值为 0 分，中间线性插值
+fn metric_score(value: f64, fair: f64, poor: f64) -> f64 {
+    let points = [(0.0, 100.0), (fair, 70.0), (poor, 40.0), (poor * 2.0, 0.0)];
+    for pair in points.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        if value <= x1 {
+            return y0 + (value.max(x0) - x0) / (x1 - x0) * (y1 - y0);
+        }
+    }
+    0.0
+}
+
+impl ScoringProfile {
+    pub fn classify(&self, latency: f64, jitter: f64, packet_loss: f64) -> ConnectionQuality {
+        let exceeds = |t: &Thresholds| latency > t.latency || jitter > t.jitter || packet_loss > t.packet_loss;
+        if exceeds(&self.poor) {
+            ConnectionQuality::Poor
+        } else if exceeds(&self.fair) {
+            ConnectionQuality::Fair
+        } else {
+            ConnectionQuality::Good
+        }
+    }
+
+    // 取三项中最差的一项，与 classify 的等级边界一致
+    pub fn score(&self, latency: f64, jitter: f64, packet_loss: f64) -> f64 {
+        metric_score(latency, self.fair.latency, self.poor.latency)
+            .min(metric_score(jitter, self.fair.jitter, self.poor.jitter))
+            .min(metric_score(packet_loss, self.fair.packet_loss, self.poor.packet_loss))
+    }
+}
+
+// ITU-T G.107 E-model 的常用简化计算，假设 G.711 编码、带丢包隐藏、随机丢包：
+// - 单向时延 = RTT / 2 + 2 × 抖动（抖动缓冲）+ 10 ms（编码）
+// - Id = 0.024d + 0.11(d - 177.3)H(d - 177.3)
+// - Ie,eff = Ie + (95 - Ie) × Ppl / (Ppl + Bpl)，G.711 的 Ie = 0、Bpl = 25.1（G.113 附录 I）
+// - R = 93.2 - Id - Ie,eff
+// 探测目标是默认网关时只反映本地链路，仅作参考
+pub fn r_factor(rtt: f64, jitter: f64, packet_loss: f64) -> f64 {
+    const IE: f64 = 0.0;
+    const BPL: f64 = 25.1;
+
+    let delay = rtt / 2.0 + jitter * 2.0 + 10.0;
+    let id = 0.024 * delay + if delay > 177.3 { 0.11 * (delay - 177.3) } else { 0.0 };
+    let ie_eff = IE + (95.0 - IE) * packet_loss / (packet_loss + BPL);
+
+    93.2 - id - ie_eff
+}
+
+// G.107 附录 B：R 因子换算 MOS
+pub fn mos(r: f64) -> f64 {
+    if r <= 0.0 {
+        1.0
+    } else if r >= 100.0 {
+        4.5
+    } else {
+        1.0 + 0.035 * r + r * (r - 60.0) * (100.0 - r) * 7e-6
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "{} != {} ± {}", actual, expected, tolerance);
+    }
+
+    fn custom() -> QualityConfig {
+        QualityConfig::Custom {
+            fair: Thresholds { latency: 20.0, jitter: 4.0, packet_loss: 1.0 },
+            poor: Thresholds { latency: 40.0, jitter: 8.0, packet_loss: 2.0 },
+        }
+    }
+
+    #[test]
+    fn presets_classify_at_their_thresholds() {
+        let browsing = QualityConfig::Browsing;
+        // 等于阈值不降级
+        assert_eq!(browsing.assess(50.0, 0.0, 0.0).0, ConnectionQuality::Good);
+        assert_eq!(browsing.assess(50.1, 0.0, 0.0).0, ConnectionQuality::Fair);
+        assert_eq!(browsing.assess(100.1, 0.0, 0.0).0, ConnectionQuality::Poor);
+        assert_eq!(browsing.assess(10.0, 0.0, 5.5).0, ConnectionQuality::Poor);
+
+        // 同样的延迟在游戏标准下更差
+        assert_eq!(QualityConfig::Gaming.assess(45.0, 2.0, 0.0).0, ConnectionQuality::Fair);
+        assert_eq!(QualityConfig::VideoCall.assess(45.0, 2.0, 0.0).0, ConnectionQuality::Good);
+        assert_eq!(QualityConfig::VideoCall.assess(45.0, 45.0, 0.0).0, ConnectionQuality::Poor);
+    }
+
+    // 旧版 background_updater 的固定规则：延迟 > 100 或丢包 > 5 为较差，延迟 > 50 或丢包 > 2 为一般
+    #[test]
+    fn browsing_keeps_legacy_latency_and_loss_boundaries() {
+        let legacy = |latency: f64, packet_loss: f64| {
+            if latency > 100.0 || packet_loss > 5.0 {
+                ConnectionQuality::Poor
+            } else if latency > 50.0 || packet_loss > 2.0 {
+                ConnectionQuality::Fair
+            } else {
+                ConnectionQuality::Good
+            }
+        };
+
+        // 抖动不超过 30 ms 时与旧版完全一致
+        for latency in [0.0, 49.9, 50.0, 50.1, 99.9, 100.0, 100.1, 300.0] {
+            for packet_loss in [0.0, 1.9, 2.0, 2.1, 4.9, 5.0, 5.1, 50.0] {
+                for jitter in [0.0, 30.0] {
+                    assert_eq!(
+                        QualityConfig::Browsing.assess(latency, jitter, packet_loss).0,
+                        legacy(latency, packet_loss),
+                        "latency {} jitter {} loss {}",
+                        latency,
+                        jitter,
+                        packet_loss
+                    );
+                }
+            }
+        }
+
+        // 抖动阈值是新增的
+        assert_eq!(QualityConfig::Browsing.assess(10.0, 30.1, 0.0).0, ConnectionQuality::Fair);
+        assert_eq!(QualityConfig::Browsing.assess(10.0, 50.1, 0.0).0, ConnectionQuality::Poor);
+    }
+
+    #[test]
+    fn worst_metric_determines_quality_and_score() {
+        let config = custom();
+        let (quality, score) = config.assess(5.0, 6.0, 0.0);
+        assert_eq!(quality, ConnectionQuality::Fair);
+        // 抖动 6 ms 在 4 和 8 之间，得分 55
+        assert_close(score, 55.0, 1e-9);
+    }
+
+    #[test]
+    fn score_interpolates_between_thresholds() {
+        let profile = custom().profile().unwrap();
+        assert_close(profile.score(0.0, 0.0, 0.0), 100.0, 1e-9);
+        assert_close(profile.score(10.0, 0.0, 0.0), 85.0, 1e-9);
+        assert_close(profile.score(20.0, 0.0, 0.0), 70.0, 1e-9);
+        assert_close(profile.score(40.0, 0.0, 0.0), 40.0, 1e-9);
+        assert_close(profile.score(60.0, 0.0, 0.0), 20.0, 1e-9);
+        assert_close(profile.score(80.0, 0.0, 0.0), 0.0, 1e-9);
+        assert_close(profile.score(500.0, 0.0, 0.0), 0.0, 1e-9);
+    }
+
+    #[test]
+    fn validate_requires_fair_below_poor() {
+        assert!(custom().validate().is_ok());
+        assert!(QualityConfig::Gaming.validate().is_ok());
+
+        let inverted = QualityConfig::Custom {
+            fair: Thresholds { latency: 50.0, jitter: 4.0, packet_loss: 1.0 },
+            poor: Thresholds { latency: 40.0, jitter: 8.0, packet_loss: 2.0 },
+        };
+        assert!(inverted.validate().is_err());
+
+        let zero = QualityConfig::Custom {
+            fair: Thresholds { latency: 0.0, jitter: 4.0, packet_loss: 1.0 },
+            poor: Thresholds { latency: 40.0, jitter: 8.0, packet_loss: 2.0 },
+        };
+        assert!(zero.validate().is_err());
+
+        assert!(QualityConfig::Mos { fair: 4.03, poor: 3.6 }.validate().is_ok());
+        assert!(QualityConfig::Mos { fair: 3.6, poor: 4.03 }.validate().is_err());
+        assert!(QualityConfig::Mos { fair: 5.0, poor: 3.6 }.validate().is_err());
+    }
+
+    #[test]
+    fn config_deserializes_by_preset_tag() {
+        let parse = |s: &str| serde_json::from_str::<QualityConfig>(s).unwrap();
+        assert_eq!(parse(r#"{"preset":"video_call"}"#), QualityConfig::VideoCall);
+        assert_eq!(parse(r#"{"preset":"mos"}"#), QualityConfig::Mos { fair: 4.03, poor: 3.6 });
+        assert_eq!(
+            parse(
+                r#"{"preset":"custom",
+                    "fair":{"latency":20,"jitter":4,"packet_loss":1},
+                    "poor":{"latency":40,"jitter":8,"packet_loss":2}}"#
+            ),
+            custom()
+        );
+    }
+
+    // G.107 附录 B 的 R → MOS 对照：90 → 4.34，80 → 4.03，70 → 3.60，60 → 3.10，50 → 2.58
+    #[test]
+    fn mos_matches_g107_reference_values() {
+        assert_close(mos(93.2), 4.41, 0.005);
+        assert_close(mos(90.0), 4.34, 0.005);
+        assert_close(mos(80.0), 4.03, 0.01);
+        assert_close(mos(70.0), 3.60, 0.005);
+        assert_close(mos(60.0), 3.10, 0.005);
+        assert_close(mos(50.0), 2.58, 0.01);
+    }
+
+    #[test]
+    fn mos_is_clamped_outside_r_range() {
+        assert_eq!(mos(-10.0), 1.0);
+        assert_eq!(mos(0.0), 1.0);
+        assert_eq!(mos(100.0), 4.5);
+        assert_eq!(mos(120.0), 4.5);
+    }
+
+    #[test]
+    fn r_factor_delay_impairment() {
+        // 没有时延和丢包时只剩编码时延 10 ms：R = 93.2 - 0.24
+        assert_close(r_factor(0.0, 0.0, 0.0), 92.96, 1e-9);
+        // 单向 150 ms（G.114 上限）：Id = 3.6
+        assert_close(r_factor(280.0, 0.0, 0.0), 89.6, 1e-9);
+        // 超过 177.3 ms 后 Id 增加 0.11 / ms：单向 200 ms 时 Id = 4.8 + 2.497
+        assert_close(r_factor(380.0, 0.0, 0.0), 85.903, 1e-9);
+        // 抖动按两倍计入抖动缓冲
+        assert_close(r_factor(0.0, 70.0, 0.0), r_factor(280.0, 0.0, 0.0), 1e-9);
+    }
+
+    #[test]
+    fn r_factor_loss_impairment_uses_g711_bpl() {
+        // Ie,eff = 95 × Ppl / (Ppl + 25.1)
+        assert_close(r_factor(0.0, 0.0, 1.0), 92.96 - 95.0 / 26.1, 1e-9);
+        assert_close(r_factor(0.0, 0.0, 25.1), 92.96 - 47.5, 1e-9);
+        // 带丢包隐藏的 G.711 在 5% 随机丢包时 MOS 约 3.9，15% 时降到 3 以下
+        assert_close(mos(r_factor(20.0, 2.0, 5.0)), 3.90, 0.01);
+        assert!(mos(r_factor(20.0, 2.0, 15.0)) < 3.0);
+    }
+
+    #[test]
+    fn mos_preset_grades_by_estimated_mos() {
+        let config = QualityConfig::Mos { fair: 4.03, poor: 3.6 };
+        let (quality, score) = config.assess(20.0, 2.0, 0.0);
+        assert_eq!(quality, ConnectionQuality::Good);
+        assert_close(score, r_factor(20.0, 2.0, 0.0), 1e-9);
+        assert_eq!(config.assess(20.0, 2.0, 5.0).0, ConnectionQuality::Fair);
+        assert_eq!(config.assess(20.0, 2.0, 15.0).0, ConnectionQuality::Poor);
+        assert_eq!(config.profile(), None);
+    }
+}
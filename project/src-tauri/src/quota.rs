@@ -0,0 +1,316 @@
+// 流量配额：按天 / 周 / 计费周期限制全部或单个网卡的用量
+//
+// 用量达到配置的百分比阈值（默认 75%、90%、100%）时发出 quota-warning 事件，
+// 每个阈值在每个周期内只提醒一次，提醒记录保存在用量数据库中，重启后不会重复提醒。
+// 同时按本周期的平均速率预计配额用完的时间。
+
+use crate::settings::ConfigFile;
+use crate::usage::{self, UsageConfig, UsageLedger};
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tracing::error;
+
+pub const CONFIG_FILE_NAME: &str = "quotas.json";
+pub const WARNING_EVENT: &str = "quota-warning";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaPeriod {
+    Day,
+    // 自然周，从周一开始
+    Week,
+    // 计费周期，起始日见 UsageConfig
+    Cycle,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Quota {
+    // 唯一名称，用于提醒记录和事件
+    pub name: String,
+    pub limit_bytes: u64,
+    pub period: QuotaPeriod,
+    // 网卡 id、名称或别名；为空时统计计入总速率的网卡
+    #[serde(default)]
+    pub interface: Option<String>,
+    // 提醒阈值，百分比
+    #[serde(default = "default_thresholds")]
+    pub thresholds: Vec<u32>,
+}
+
+fn default_thresholds() -> Vec<u32> {
+    vec![75, 90, 100]
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct QuotaConfig {
+    #[serde(default)]
+    pub quotas: Vec<Quota>,
+}
+
+impl ConfigFile for QuotaConfig {
+    const FILE_NAME: &'static str = CONFIG_FILE_NAME;
+    const LABEL: &'static str = "配额配置";
+
+    fn validate(&self) -> Result<(), String> {
+        let mut names = HashSet::new();
+        for quota in &self.quotas {
+            if quota.name.trim().is_empty() {
+                return Err("配额名称不能为空".to_string());
+            }
+            if !names.insert(quota.name.as_str()) {
+                return Err(format!("配额名称重复: {}", quota.name));
+            }
+            if quota.limit_bytes == 0 {
+                return Err(format!("配额 {} 的上限必须大于 0", quota.name));
+            }
+            if quota.thresholds.contains(&0) {
+                return Err(format!("配额 {} 的提醒阈值必须大于 0", quota.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct QuotaStatus {
+    pub name: String,
+    pub interface: Option<String>,
+    pub period: QuotaPeriod,
+    // 当前周期 [起始日期, 下一周期起始日期)，YYYY-MM-DD
+    pub period_start: String,
+    pub period_end: String,
+    pub used_bytes: u64,
+    pub limit_bytes: u64,
+    pub percent: f64,
+    pub exceeded: bool,
+    // 按本周期平均速率预计用完的时间，Unix 毫秒；本周期内不会用完或已用完时为 None
+    pub projected_exhaustion: Option<i64>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct QuotaWarning {
+    // 本次越过的阈值，百分比
+    pub threshold: u32,
+    #[serde(flatten)]
+    pub status: QuotaStatus,
+}
+
+pub fn period_bounds(period: QuotaPeriod, today: NaiveDate, usage_config: &UsageConfig) -> (NaiveDate, NaiveDate) {
+    match period {
+        QuotaPeriod::Day => (today, today + Duration::days(1)),
+        QuotaPeriod::Week => usage::week_bounds(today),
+        QuotaPeriod::Cycle => usage::cycle_bounds(today, usage_config.cycle_start_day),
+    }
+}
+
+fn local_midnight(day: NaiveDate) -> Option<DateTime<Local>> {
+    Local.from_local_datetime(&day.and_hms_opt(0, 0, 0)?).earliest()
+}
+
+// 以周期开始到现在的平均速率外推，预计用完的时间点必须落在本周期内
+pub fn project_exhaustion(
+    used: u64,
+    limit: u64,
+    period_start: DateTime<Local>,
+    period_end: DateTime<Local>,
+    now: DateTime<Local>,
+) -> Option<DateTime<Local>> {
+    if used == 0 || used >= limit {
+        return None;
+    }
+
+    // 周期刚开始时样本太少，至少按一分钟计算
+    let elapsed = (now - period_start).num_seconds().max(60) as f64;
+    let rate = used as f64 / elapsed;
+    let remaining = (limit - used) as f64 / rate;
+    let exhaustion = now + Duration::seconds(remaining.min(i64::MAX as f64 / 1000.0) as i64);
+
+    if exhaustion < period_end {
+        Some(exhaustion)
+    } else {
+        None
+    }
+}
+
+// interface_id 为配额对应网卡的 id（已经按名称 / 别名解析过）
+pub fn status(
+    quota: &Quota,
+    interface_id: Option<&str>,
+    ledger: &UsageLedger,
+    usage_config: &UsageConfig,
+    now: DateTime<Local>,
+) -> Result<QuotaStatus, String> {
+    let (start, end) = period_bounds(quota.period, now.date_naive(), usage_config);
+    let used = ledger.total_between(start, end, interface_id)?.total_bytes;
+
+    let projected = match (local_midnight(start), local_midnight(end)) {
+        (Some(start_time), Some(end_time)) => {
+            project_exhaustion(used, quota.limit_bytes, start_time, end_time, now)
+        }
+        _ => None,
+    };
+
+    Ok(QuotaStatus {
+        name: quota.name.clone(),
+        interface: quota.interface.clone(),
+        period: quota.period,
+        period_start: start.to_string(),
+        period_end: end.to_string(),
+        used_bytes: used,
+        limit_bytes: quota.limit_bytes,
+        percent: used as f64 * 100.0 / quota.limit_bytes as f64,
+        exceeded: used >= quota.limit_bytes,
+        projected_exhaustion: projected.map(|t| t.timestamp_millis()),
+    })
+}
+
+// 返回本次新越过阈值的配额，并记录已提醒的阈值
+pub fn check(
+    config: &QuotaConfig,
+    resolve_interface: impl Fn(&str) -> String,
+    ledger: &UsageLedger,
+    usage_config: &UsageConfig,
+    now: DateTime<Local>,
+) -> Vec<QuotaWarning> {
+    let mut warnings = Vec::new();
+
+    for quota in &config.quotas {
+        let interface_id = quota.interface.as_deref().map(&resolve_interface);
+        let result = status(quota, interface_id.as_deref(), ledger, usage_config, now).and_then(|status| {
+            let start = period_bounds(quota.period, now.date_naive(), usage_config).0;
+            let crossed = quota
+                .thresholds
+                .iter()
+                .copied()
+                .filter(|&t| status.percent >= t as f64)
+                .max()
+                .unwrap_or(0);
+
+            if crossed > ledger.alerted_threshold(&quota.name, start)? {
+                ledger.set_alerted_threshold(&quota.name, start, crossed)?;
+                warnings.push(QuotaWarning { threshold: crossed, status });
+            }
+            Ok(())
+        });
+
+        if let Err(e) = result {
+            error!("Quota {}: {}", quota.name, e);
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(s: &str) -> DateTime<Local> {
+        let naive = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+        Local.from_local_datetime(&naive).earliest().unwrap()
+    }
+
+    fn quota(name: &str, limit_bytes: u64, period: QuotaPeriod) -> Quota {
+        Quota { name: name.to_string(), limit_bytes, period, interface: None, thresholds: default_thresholds() }
+    }
+
+    fn config(quotas: Vec<Quota>) -> QuotaConfig {
+        QuotaConfig { quotas }
+    }
+
+    fn add(ledger: &mut UsageLedger, day: &str, bytes: u64) {
+        ledger.record(day.parse().unwrap(), (bytes, 0), &[]).unwrap();
+    }
+
+    fn thresholds(warnings: &[QuotaWarning]) -> Vec<(String, u32)> {
+        warnings.iter().map(|w| (w.status.name.clone(), w.threshold)).collect()
+    }
+
+    #[test]
+    fn project_exhaustion_extrapolates_average_rate() {
+        let start = local("2024-05-01 00:00");
+        let end = local("2024-05-31 00:00");
+
+        // 10 天用了一半，按同样速率再过 10 天用完
+        let projected = project_exhaustion(50, 100, start, end, local("2024-05-11 00:00")).unwrap();
+        assert_eq!(projected, local("2024-05-21 00:00"));
+
+        // 预计时间落在周期之后
+        assert_eq!(project_exhaustion(50, 100, start, end, local("2024-05-21 00:00")), None);
+    }
+
+    #[test]
+    fn project_exhaustion_ignores_unused_and_exhausted_quotas() {
+        let start = local("2024-05-01 00:00");
+        let end = local("2024-05-31 00:00");
+        let now = local("2024-05-11 00:00");
+        assert_eq!(project_exhaustion(0, 100, start, end, now), None);
+        assert_eq!(project_exhaustion(100, 100, start, end, now), None);
+        assert_eq!(project_exhaustion(150, 100, start, end, now), None);
+    }
+
+    #[test]
+    fn project_exhaustion_uses_at_least_one_minute() {
+        // 周期刚开始就用了 1%：按一分钟计算，约 99 分钟后用完
+        let start = local("2024-05-01 00:00");
+        let projected = project_exhaustion(1, 100, start, local("2024-05-31 00:00"), start).unwrap();
+        assert_eq!(projected, start + Duration::minutes(99));
+    }
+
+    #[test]
+    fn check_fires_each_threshold_once_per_cycle() {
+        let mut ledger = UsageLedger::in_memory();
+        let usage_config = UsageConfig { cycle_start_day: 1 };
+        let config = config(vec![quota("monthly", 1000, QuotaPeriod::Cycle)]);
+        let check_at = |ledger: &UsageLedger, now: &str| {
+            thresholds(&check(&config, |entry| entry.to_string(), ledger, &usage_config, local(now)))
+        };
+
+        add(&mut ledger, "2024-05-03", 500);
+        assert!(check_at(&ledger, "2024-05-03 12:00").is_empty());
+
+        add(&mut ledger, "2024-05-10", 300);
+        assert_eq!(check_at(&ledger, "2024-05-10 12:00"), [("monthly".to_string(), 75)]);
+        assert!(check_at(&ledger, "2024-05-10 12:01").is_empty());
+
+        // 一次越过 90% 和 100% 时只提醒最高的阈值
+        add(&mut ledger, "2024-05-20", 250);
+        assert_eq!(check_at(&ledger, "2024-05-20 12:00"), [("monthly".to_string(), 100)]);
+        assert!(check_at(&ledger, "2024-05-25 12:00").is_empty());
+
+        // 新周期重新提醒
+        add(&mut ledger, "2024-06-02", 800);
+        assert_eq!(check_at(&ledger, "2024-06-02 12:00"), [("monthly".to_string(), 75)]);
+    }
+
+    #[test]
+    fn check_tracks_quotas_independently() {
+        let mut ledger = UsageLedger::in_memory();
+        let usage_config = UsageConfig::default();
+        let mut per_interface = quota("wifi", 100, QuotaPeriod::Day);
+        per_interface.interface = Some("Wi-Fi".to_string());
+        let config = config(vec![quota("daily", 1000, QuotaPeriod::Day), per_interface]);
+
+        let day = "2024-05-20".parse().unwrap();
+        ledger.record(day, (950, 0), &[("wlan0".to_string(), 95, 0)]).unwrap();
+
+        // 配额中的网卡名称解析为 id
+        let resolve = |entry: &str| if entry == "Wi-Fi" { "wlan0".to_string() } else { entry.to_string() };
+        let warnings = check(&config, resolve, &ledger, &usage_config, local("2024-05-20 12:00"));
+        assert_eq!(thresholds(&warnings), [("daily".to_string(), 90), ("wifi".to_string(), 90)]);
+        assert_eq!(warnings[1].status.used_bytes, 95);
+    }
+
+    #[test]
+    fn validate_rejects_bad_quotas() {
+        assert!(config(vec![quota("a", 1, QuotaPeriod::Day), quota("b", 1, QuotaPeriod::Week)]).validate().is_ok());
+        assert!(config(vec![quota(" ", 1, QuotaPeriod::Day)]).validate().is_err());
+        assert!(config(vec![quota("a", 1, QuotaPeriod::Day), quota("a", 2, QuotaPeriod::Day)]).validate().is_err());
+        assert!(config(vec![quota("a", 0, QuotaPeriod::Day)]).validate().is_err());
+
+        let mut zero_threshold = quota("a", 1, QuotaPeriod::Day);
+        zero_threshold.thresholds = vec![0, 50];
+        assert!(config(vec![zero_threshold]).validate().is_err());
+    }
+}
@@ -0,0 +1,473 @@
+// 应用设置：保存在平台配置目录下的 settings.json
+//
+// 所有字段都有默认值，文件中缺少的字段按默认值补齐；加载和修改时都会校验，
+// 校验失败时保留当前设置。文件被外部修改时自动重新加载，
+// 后台线程和各个获取函数每次使用时读取 current()，修改后立即生效，无需重启。
+//
+// 同一目录下单独保存的 JSON 配置（网卡筛选、计费周期、配额、告警规则、评分标准）实现 ConfigFile，
+// 共用这里的读取、校验和保存逻辑，由同一个目录监听器在外部修改时重新加载。
+
+use crate::public_ip::ProviderConfig;
+use crate::{logging, probe};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, RwLock};
+use std::time::Duration;
+use tracing::{error, warn};
+
+pub const FILE_NAME: &str = "settings.json";
+pub const CHANGED_EVENT: &str = "settings-changed";
+// 其它配置文件被外部修改并重新加载后发出，payload 为文件名
+pub const CONFIG_CHANGED_EVENT: &str = "config-changed";
+
+// 设置格式版本，字段含义变化时递增
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    pub log: LogSettings,
+    pub sampling: SamplingSettings,
+    pub public_ip: PublicIpSettings,
+    pub ui: UiSettings,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct LogSettings {
+    // 为空时使用平台日志目录下的 network-stats.log
+    pub path: Option<String>,
+    // EnvFilter 语法，例如 "info" 或 "warn,floating_stats_lib::probe=debug"；
+    // 每秒的采样和接口统计在 trace 级别
+    pub level: String,
+    pub format: LogFormat,
+    // 文件超过该大小（MB）时滚动，0 表示不按大小滚动
+    pub max_file_mb: u64,
+    // 跨天时滚动
+    pub rotate_daily: bool,
+    // 归档文件用 gzip 压缩
+    pub compress: bool,
+    // 保留的归档文件个数，更早的删除
+    pub max_archives: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    // 每行一个 JSON 对象，便于用工具检索
+    Json,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SamplingSettings {
+    // 后台计算速度和统计的间隔
+    pub sample_interval_ms: u64,
+    // 延迟探测间隔、统计窗口（次数）和单次超时
+    pub probe_interval_ms: u64,
+    pub probe_window: usize,
+    pub probe_timeout_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct PublicIpSettings {
+    // 按顺序尝试，被限流的接口在冷却期内跳过
+    pub providers: Vec<ProviderConfig>,
+    // 单个接口的连接和响应超时
+    pub timeout_ms: u64,
+    // 离线地理位置数据库（GeoLite2 / DB-IP 的 .mmdb 文件路径），城市库和 ASN 库可同时配置
+    pub geo_databases: Vec<String>,
+    // 后台检查公网 IP 是否变化的间隔（秒），0 表示不检查；默认路由网卡变化时立即检查
+    pub watch_interval_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct UiSettings {
+    pub weather_refresh_minutes: u64,
+    pub usage_refresh_seconds: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            version: VERSION,
+            log: LogSettings::default(),
+            sampling: SamplingSettings::default(),
+            public_ip: PublicIpSettings::default(),
+            ui: UiSettings::default(),
+        }
+    }
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        LogSettings {
+            path: None,
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            max_file_mb: 10,
+            rotate_daily: true,
+            compress: true,
+            max_archives: 7,
+        }
+    }
+}
+
+impl Default for SamplingSettings {
+    fn default() -> Self {
+        SamplingSettings {
+            sample_interval_ms: 1000,
+            probe_interval_ms: 1000,
+            probe_window: probe::DEFAULT_WINDOW,
+            probe_timeout_ms: 2000,
+        }
+    }
+}
+
+impl Default for PublicIpSettings {
+    fn default() -> Self {
+        PublicIpSettings {
+            providers: ProviderConfig::defaults(),
+            timeout_ms: 5000,
+            geo_databases: Vec::new(),
+            watch_interval_secs: 300,
+        }
+    }
+}
+
+impl Default for UiSettings {
+    fn default() -> Self {
+        UiSettings {
+            weather_refresh_minutes: 10,
+            usage_refresh_seconds: 10,
+        }
+    }
+}
+
+fn check_range<T: PartialOrd + std::fmt::Display>(name: &str, value: T, min: T, max: T) -> Result<(), String> {
+    if value < min || value > max {
+        Err(format!("{} 必须在 {} 到 {} 之间，当前为 {}", name, min, max, value))
+    } else {
+        Ok(())
+    }
+}
+
+// 配置目录中的 JSON 配置文件，settings.json 以及各功能单独保存的配置
+pub trait ConfigFile: Serialize + DeserializeOwned + Default {
+    const FILE_NAME: &'static str;
+    // 错误信息中使用的名称，例如 "配额配置"
+    const LABEL: &'static str;
+
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// 文件不存在时返回默认值，读取、解析或校验失败时返回错误
+pub fn read_json<T: ConfigFile>(path: &Path) -> Result<T, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => return Err(format!("读取{}失败: {}", T::LABEL, e)),
+    };
+    let value: T = serde_json::from_str(&text).map_err(|e| format!("{}格式错误: {}", T::LABEL, e))?;
+    value.validate()?;
+    Ok(value)
+}
+
+// 启动时读取：内容无效时记录警告并使用默认值
+pub fn load_json<T: ConfigFile>(path: &Path) -> T {
+    read_json(path).unwrap_or_else(|e| {
+        warn!("Invalid {}, using defaults: {}", path.display(), e);
+        T::default()
+    })
+}
+
+pub fn save_json<T: ConfigFile>(value: &T, path: &Path) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("创建配置目录失败: {}", e))?;
+    }
+    let text = serde_json::to_string_pretty(value).map_err(|e| format!("序列化{}失败: {}", T::LABEL, e))?;
+    fs::write(path, text).map_err(|e| format!("保存{}失败: {}", T::LABEL, e))
+}
+
+impl ConfigFile for Settings {
+    const FILE_NAME: &'static str = FILE_NAME;
+    const LABEL: &'static str = "设置";
+
+    fn validate(&self) -> Result<(), String> {
+        if self.version > VERSION {
+            return Err(format!("不支持的设置版本 {}，当前版本为 {}", self.version, VERSION));
+        }
+
+        if self.log.path.as_ref().is_some_and(|path| path.trim().is_empty()) {
+            return Err("日志路径不能为空字符串，使用默认位置时请删除该项".to_string());
+        }
+        logging::parse_filter(&self.log.level)?;
+        check_range("log.max_file_mb", self.log.max_file_mb, 0, 1024)?;
+        check_range("log.max_archives", self.log.max_archives, 1, 1000)?;
+
+        let sampling = &self.sampling;
+        check_range("sampling.sample_interval_ms", sampling.sample_interval_ms, 200, 60_000)?;
+        check_range("sampling.probe_interval_ms", sampling.probe_interval_ms, 100, 60_000)?;
+        check_range("sampling.probe_window", sampling.probe_window, 1, 3600)?;
+        check_range("sampling.probe_timeout_ms", sampling.probe_timeout_ms, 100, 10_000)?;
+
+        ProviderConfig::validate_all(&self.public_ip.providers)?;
+        check_range("public_ip.timeout_ms", self.public_ip.timeout_ms, 500, 60_000)?;
+        if self.public_ip.watch_interval_secs != 0 {
+            check_range("public_ip.watch_interval_secs", self.public_ip.watch_interval_secs, 30, 24 * 3600)?;
+        }
+        if self.public_ip.geo_databases.iter().any(|path| path.trim().is_empty()) {
+            return Err("地理位置数据库路径不能为空".to_string());
+        }
+
+        check_range("ui.weather_refresh_minutes", self.ui.weather_refresh_minutes, 1, 24 * 60)?;
+        check_range("ui.usage_refresh_seconds", self.ui.usage_refresh_seconds, 1, 3600)?;
+        Ok(())
+    }
+}
+
+impl Settings {
+    // 把 patch 中的字段合并到当前设置上，对象按字段递归合并，其余类型直接替换
+    pub fn merged(&self, patch: serde_json::Value) -> Result<Settings, String> {
+        let mut value = serde_json::to_value(self).map_err(|e| format!("序列化设置失败: {}", e))?;
+        merge_json(&mut value, patch);
+        let settings: Settings = serde_json::from_value(value).map_err(|e| format!("设置格式错误: {}", e))?;
+        settings.validate()?;
+        Ok(settings)
+    }
+}
+
+impl PublicIpSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    pub fn watch_interval(&self) -> Option<Duration> {
+        (self.watch_interval_secs > 0).then(|| Duration::from_secs(self.watch_interval_secs))
+    }
+}
+
+impl SamplingSettings {
+    pub fn sample_interval(&self) -> Duration {
+        Duration::from_millis(self.sample_interval_ms)
+    }
+
+    pub fn probe_interval(&self) -> Duration {
+        Duration::from_millis(self.probe_interval_ms)
+    }
+
+    pub fn probe_timeout(&self) -> Duration {
+        Duration::from_millis(self.probe_timeout_ms)
+    }
+}
+
+fn merge_json(target: &mut serde_json::Value, patch: serde_json::Value) {
+    match (target, patch) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(patch)) => {
+            for (key, value) in patch {
+                match target.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+static CURRENT: LazyLock<RwLock<Settings>> = LazyLock::new(|| RwLock::new(Settings::default()));
+
+pub fn current() -> Settings {
+    with(Settings::clone)
+}
+
+// 只读取部分字段时避免整体 clone，例如每条日志读取日志路径
+pub fn with<R>(f: impl FnOnce(&Settings) -> R) -> R {
+    match CURRENT.read() {
+        Ok(settings) => f(&settings),
+        Err(poisoned) => f(&poisoned.into_inner()),
+    }
+}
+
+// 替换当前设置，返回是否有变化
+pub fn replace(settings: Settings) -> bool {
+    let mut current = match CURRENT.write() {
+        Ok(current) => current,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    if *current == settings {
+        return false;
+    }
+    *current = settings;
+    true
+}
+
+// 监听配置目录（编辑器保存时通常是替换文件），目录中的文件变化时用文件名调用 on_change，
+// 由调用方按文件名重新读取对应的配置
+pub fn watch(dir: PathBuf, on_change: impl Fn(&str) + Send + 'static) -> Result<RecommendedWatcher, String> {
+    fs::create_dir_all(&dir).map_err(|e| format!("创建配置目录失败: {}", e))?;
+
+    let watch_dir = dir.clone();
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+        let event = match result {
+            Ok(event) => event,
+            Err(e) => {
+                error!("Config watcher error: {}", e);
+                return;
+            }
+        };
+        if event.kind.is_access() {
+            return;
+        }
+
+        let mut names: Vec<&str> = event
+            .paths
+            .iter()
+            .filter(|path| path.parent() == Some(watch_dir.as_path()))
+            .filter_map(|path| path.file_name()?.to_str())
+            .collect();
+        names.dedup();
+        for name in names {
+            on_change(name);
+        }
+    })
+    .map_err(|e| format!("监听配置目录失败: {}", e))?;
+
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .map_err(|e| format!("监听配置目录失败: {}", e))?;
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // 每个测试使用独立的临时目录，结束时删除
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("floating-stats-settings-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert_eq!(Settings::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn merged_patches_nested_fields_and_keeps_the_rest() {
+        let current = Settings::default();
+        let merged = current
+            .merged(json!({"ui": {"weather_refresh_minutes": 30}, "log": {"level": "debug"}}))
+            .unwrap();
+
+        assert_eq!(merged.ui.weather_refresh_minutes, 30);
+        assert_eq!(merged.ui.usage_refresh_seconds, current.ui.usage_refresh_seconds);
+        assert_eq!(merged.log.level, "debug");
+        assert_eq!(merged.log.max_archives, current.log.max_archives);
+        assert_eq!(merged.sampling, current.sampling);
+
+        // 非对象的值直接替换
+        let merged = current.merged(json!({"public_ip": {"geo_databases": ["/tmp/city.mmdb"]}})).unwrap();
+        assert_eq!(merged.public_ip.geo_databases, ["/tmp/city.mmdb"]);
+        let merged = merged.merged(json!({"log": {"path": null}})).unwrap();
+        assert_eq!(merged.log.path, None);
+    }
+
+    #[test]
+    fn merged_rejects_invalid_patches() {
+        let current = Settings::default();
+        assert!(current.merged(json!({"sampling": {"sample_interval_ms": 50}})).is_err());
+        assert!(current.merged(json!({"ui": {"usage_refresh_seconds": "10"}})).is_err());
+        assert!(current.merged(json!({"log": {"level": "not a level=="}})).is_err());
+        assert!(current.merged(json!({"version": VERSION + 1})).is_err());
+    }
+
+    #[test]
+    fn validate_checks_ranges() {
+        let with = |f: fn(&mut Settings)| {
+            let mut settings = Settings::default();
+            f(&mut settings);
+            settings.validate()
+        };
+
+        assert!(with(|s| s.log.max_archives = 0).is_err());
+        assert!(with(|s| s.log.max_file_mb = 2048).is_err());
+        assert!(with(|s| s.log.path = Some("  ".to_string())).is_err());
+        assert!(with(|s| s.sampling.probe_window = 0).is_err());
+        assert!(with(|s| s.sampling.probe_timeout_ms = 99).is_err());
+        assert!(with(|s| s.public_ip.timeout_ms = 100).is_err());
+        assert!(with(|s| s.public_ip.geo_databases = vec![String::new()]).is_err());
+        assert!(with(|s| s.ui.weather_refresh_minutes = 0).is_err());
+
+        // 0 表示不检查公网 IP，其它值至少 30 秒
+        assert_eq!(with(|s| s.public_ip.watch_interval_secs = 0), Ok(()));
+        assert!(with(|s| s.public_ip.watch_interval_secs = 10).is_err());
+
+        // 边界值有效
+        assert_eq!(with(|s| s.sampling.sample_interval_ms = 200), Ok(()));
+        assert_eq!(with(|s| s.log.max_file_mb = 0), Ok(()));
+    }
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let settings: Settings = serde_json::from_str(r#"{"ui": {"usage_refresh_seconds": 5}}"#).unwrap();
+        assert_eq!(settings.ui.usage_refresh_seconds, 5);
+        assert_eq!(settings.ui.weather_refresh_minutes, UiSettings::default().weather_refresh_minutes);
+        assert_eq!(settings.version, VERSION);
+        assert_eq!(settings.log, LogSettings::default());
+    }
+
+    #[test]
+    fn json_files_round_trip() {
+        let dir = TempDir::new("round-trip");
+        let path = dir.0.join("nested").join(FILE_NAME);
+
+        // 文件不存在时使用默认值
+        assert_eq!(read_json::<Settings>(&path), Ok(Settings::default()));
+
+        let settings = Settings::default().merged(json!({"ui": {"weather_refresh_minutes": 60}})).unwrap();
+        save_json(&settings, &path).unwrap();
+        assert_eq!(read_json::<Settings>(&path), Ok(settings));
+    }
+
+    #[test]
+    fn invalid_json_files_are_rejected() {
+        let dir = TempDir::new("invalid");
+        fs::create_dir_all(&dir.0).unwrap();
+        let path = dir.0.join(FILE_NAME);
+
+        fs::write(&path, "{").unwrap();
+        assert!(read_json::<Settings>(&path).unwrap_err().starts_with("设置格式错误"));
+        assert_eq!(load_json::<Settings>(&path), Settings::default());
+
+        // 格式正确但校验失败
+        fs::write(&path, r#"{"sampling": {"probe_window": 0}}"#).unwrap();
+        assert!(read_json::<Settings>(&path).is_err());
+        assert_eq!(load_json::<Settings>(&path), Settings::default());
+    }
+}
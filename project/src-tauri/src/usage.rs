@@ -0,0 +1,330 @@
+// 流量用量统计：按本地日期累计计入总速率的网卡收发字节数，支持按计费周期汇总
+//
+// 字节数来自后台线程按网卡计算的增量（已处理计数器回绕和重置），
+// 每天一行保存在 SQLite 中，重启后继续累计。应用未运行期间的流量不计入。
+// 另外按网卡分别累计，供按网卡设置的配额使用。
+
+use crate::settings::ConfigFile;
+use chrono::{Datelike, Duration, NaiveDate};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+pub const FILE_NAME: &str = "usage.sqlite3";
+pub const CONFIG_FILE_NAME: &str = "usage.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UsageConfig {
+    // 计费周期从每月几号开始（1-31），当月没有这一天时从月末开始
+    #[serde(default = "default_cycle_start_day")]
+    pub cycle_start_day: u32,
+}
+
+fn default_cycle_start_day() -> u32 {
+    1
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        UsageConfig { cycle_start_day: default_cycle_start_day() }
+    }
+}
+
+impl ConfigFile for UsageConfig {
+    const FILE_NAME: &'static str = CONFIG_FILE_NAME;
+    const LABEL: &'static str = "用量配置";
+
+    fn validate(&self) -> Result<(), String> {
+        if (1..=31).contains(&self.cycle_start_day) {
+            Ok(())
+        } else {
+            Err(format!("计费周期起始日必须在 1-31 之间: {}", self.cycle_start_day))
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default)]
+pub struct UsageTotal {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub total_bytes: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct UsageSummary {
+    pub today: UsageTotal,
+    // 本周从周一开始
+    pub week: UsageTotal,
+    pub cycle: UsageTotal,
+    // 当前计费周期的起始日期和下一周期的起始日期，YYYY-MM-DD
+    pub cycle_start: String,
+    pub cycle_end: String,
+}
+
+// 某月第 day 天，超过当月天数时取月末
+fn clamped_date(year: i32, month: u32, day: u32) -> NaiveDate {
+    (1..=day)
+        .rev()
+        .find_map(|d| NaiveDate::from_ymd_opt(year, month, d))
+        .unwrap_or_default()
+}
+
+fn add_months(year: i32, month: u32, delta: i32) -> (i32, u32) {
+    let index = year * 12 + month as i32 - 1 + delta;
+    (index.div_euclid(12), index.rem_euclid(12) as u32 + 1)
+}
+
+// 返回包含 today 的计费周期 [起始日期, 下一周期起始日期)
+pub fn cycle_bounds(today: NaiveDate, start_day: u32) -> (NaiveDate, NaiveDate) {
+    let this_month = clamped_date(today.year(), today.month(), start_day);
+    let start_month = if today >= this_month { 0 } else { -1 };
+
+    let (year, month) = add_months(today.year(), today.month(), start_month);
+    let (next_year, next_month) = add_months(today.year(), today.month(), start_month + 1);
+    (clamped_date(year, month, start_day), clamped_date(next_year, next_month, start_day))
+}
+
+// 返回包含 today 的自然周 [周一, 下周一)
+pub fn week_bounds(today: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let start = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    (start, start + Duration::days(7))
+}
+
+pub struct UsageLedger {
+    conn: Connection,
+}
+
+impl UsageLedger {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("创建数据目录失败: {}", e))?;
+        }
+        let conn = Connection::open(path).map_err(|e| format!("打开用量数据库失败: {}", e))?;
+        Self::init(conn)
+    }
+
+    fn init(conn: Connection) -> Result<Self, String> {
+        // 与历史数据库相同：WAL + NORMAL，每秒一次小事务不需要每次都 fsync
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .and_then(|_| conn.pragma_update(None, "synchronous", "NORMAL"))
+            .map_err(|e| format!("配置用量数据库失败: {}", e))?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS usage_daily (
+                day TEXT PRIMARY KEY,
+                rx_bytes INTEGER NOT NULL,
+                tx_bytes INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS usage_interface_daily (
+                day TEXT NOT NULL,
+                interface TEXT NOT NULL,
+                rx_bytes INTEGER NOT NULL,
+                tx_bytes INTEGER NOT NULL,
+                PRIMARY KEY (day, interface)
+            );
+            CREATE TABLE IF NOT EXISTS quota_alerts (
+                quota TEXT NOT NULL,
+                period_start TEXT NOT NULL,
+                threshold INTEGER NOT NULL,
+                PRIMARY KEY (quota, period_start)
+            )",
+        )
+        .map_err(|e| format!("创建用量数据表失败: {}", e))?;
+        Ok(UsageLedger { conn })
+    }
+
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        Self::init(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    // 一次采样的增量：total 为计入总速率的网卡合计，interfaces 为各网卡 (id, 接收, 发送)
+    // 在同一个事务中写入，增量为 0 的行跳过
+    pub fn record(&mut self, day: NaiveDate, total: (u64, u64), interfaces: &[(String, u64, u64)]) -> Result<(), String> {
+        let changed: Vec<_> = interfaces.iter().filter(|(_, rx, tx)| *rx != 0 || *tx != 0).collect();
+        if total == (0, 0) && changed.is_empty() {
+            return Ok(());
+        }
+
+        let day = day.to_string();
+        let tx = self.conn.transaction().map_err(|e| format!("写入用量数据失败: {}", e))?;
+
+        if total != (0, 0) {
+            tx.execute(
+                "INSERT INTO usage_daily (day, rx_bytes, tx_bytes) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(day) DO UPDATE SET
+                    rx_bytes = rx_bytes + excluded.rx_bytes,
+                    tx_bytes = tx_bytes + excluded.tx_bytes",
+                params![day, total.0 as i64, total.1 as i64],
+            )
+            .map_err(|e| format!("写入用量数据失败: {}", e))?;
+        }
+
+        if !changed.is_empty() {
+            let mut stmt = tx
+                .prepare_cached(
+                    "INSERT INTO usage_interface_daily (day, interface, rx_bytes, tx_bytes) VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT(day, interface) DO UPDATE SET
+                        rx_bytes = rx_bytes + excluded.rx_bytes,
+                        tx_bytes = tx_bytes + excluded.tx_bytes",
+                )
+                .map_err(|e| format!("写入用量数据失败: {}", e))?;
+            for (interface, rx_bytes, tx_bytes) in changed {
+                stmt.execute(params![day, interface, *rx_bytes as i64, *tx_bytes as i64])
+                    .map_err(|e| format!("写入用量数据失败: {}", e))?;
+            }
+        }
+
+        tx.commit().map_err(|e| format!("写入用量数据失败: {}", e))
+    }
+
+    // [from, to) 区间内的合计；interface 为 None 时是计入总速率的网卡合计
+    pub fn total_between(&self, from: NaiveDate, to: NaiveDate, interface: Option<&str>) -> Result<UsageTotal, String> {
+        let from = from.to_string();
+        let to = to.to_string();
+        let row = |row: &rusqlite::Row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?));
+        let result = match interface {
+            None => self.conn.query_row(
+                "SELECT COALESCE(SUM(rx_bytes), 0), COALESCE(SUM(tx_bytes), 0)
+                 FROM usage_daily WHERE day >= ?1 AND day < ?2",
+                params![from, to],
+                row,
+            ),
+            Some(interface) => self.conn.query_row(
+                "SELECT COALESCE(SUM(rx_bytes), 0), COALESCE(SUM(tx_bytes), 0)
+                 FROM usage_interface_daily WHERE day >= ?1 AND day < ?2 AND interface = ?3",
+                params![from, to, interface],
+                row,
+            ),
+        };
+        let (rx, tx) = result.map_err(|e| format!("查询用量数据失败: {}", e))?;
+
+        let (rx_bytes, tx_bytes) = (rx as u64, tx as u64);
+        Ok(UsageTotal { rx_bytes, tx_bytes, total_bytes: rx_bytes + tx_bytes })
+    }
+
+    pub fn summary(&self, today: NaiveDate, config: &UsageConfig) -> Result<UsageSummary, String> {
+        let tomorrow = today + Duration::days(1);
+        let (week_start, _) = week_bounds(today);
+        let (cycle_start, cycle_end) = cycle_bounds(today, config.cycle_start_day);
+
+        Ok(UsageSummary {
+            today: self.total_between(today, tomorrow, None)?,
+            week: self.total_between(week_start, tomorrow, None)?,
+            cycle: self.total_between(cycle_start, cycle_end, None)?,
+            cycle_start: cycle_start.to_string(),
+            cycle_end: cycle_end.to_string(),
+        })
+    }
+
+    // 配额在某个周期内已经提醒过的最高阈值（百分比），没有提醒过为 0
+    pub fn alerted_threshold(&self, quota: &str, period_start: NaiveDate) -> Result<u32, String> {
+        self.conn
+            .query_row(
+                "SELECT COALESCE(MAX(threshold), 0) FROM quota_alerts WHERE quota = ?1 AND period_start = ?2",
+                params![quota, period_start.to_string()],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("查询配额提醒失败: {}", e))
+    }
+
+    pub fn set_alerted_threshold(&self, quota: &str, period_start: NaiveDate, threshold: u32) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO quota_alerts (quota, period_start, threshold) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(quota, period_start) DO UPDATE SET threshold = excluded.threshold",
+                params![quota, period_start.to_string(), threshold],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("保存配额提醒失败: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn cycle_bounds_within_month() {
+        assert_eq!(cycle_bounds(date("2024-05-20"), 15), (date("2024-05-15"), date("2024-06-15")));
+        assert_eq!(cycle_bounds(date("2024-05-15"), 15), (date("2024-05-15"), date("2024-06-15")));
+        assert_eq!(cycle_bounds(date("2024-05-14"), 15), (date("2024-04-15"), date("2024-05-15")));
+        assert_eq!(cycle_bounds(date("2024-05-01"), 1), (date("2024-05-01"), date("2024-06-01")));
+    }
+
+    #[test]
+    fn cycle_day_31_clamps_to_end_of_short_months() {
+        // 闰年二月
+        assert_eq!(cycle_bounds(date("2024-02-29"), 31), (date("2024-02-29"), date("2024-03-31")));
+        assert_eq!(cycle_bounds(date("2024-02-28"), 31), (date("2024-01-31"), date("2024-02-29")));
+        // 平年二月
+        assert_eq!(cycle_bounds(date("2023-02-28"), 31), (date("2023-02-28"), date("2023-03-31")));
+        assert_eq!(cycle_bounds(date("2023-03-30"), 31), (date("2023-02-28"), date("2023-03-31")));
+        assert_eq!(cycle_bounds(date("2024-04-30"), 31), (date("2024-04-30"), date("2024-05-31")));
+    }
+
+    #[test]
+    fn cycle_bounds_roll_over_year() {
+        assert_eq!(cycle_bounds(date("2024-12-20"), 15), (date("2024-12-15"), date("2025-01-15")));
+        assert_eq!(cycle_bounds(date("2025-01-10"), 15), (date("2024-12-15"), date("2025-01-15")));
+        assert_eq!(cycle_bounds(date("2024-12-31"), 31), (date("2024-12-31"), date("2025-01-31")));
+    }
+
+    #[test]
+    fn week_bounds_start_on_monday() {
+        // 2024-01-01 是周一
+        assert_eq!(week_bounds(date("2024-01-01")), (date("2024-01-01"), date("2024-01-08")));
+        assert_eq!(week_bounds(date("2024-01-07")), (date("2024-01-01"), date("2024-01-08")));
+        // 跨年的一周
+        assert_eq!(week_bounds(date("2025-01-01")), (date("2024-12-30"), date("2025-01-06")));
+    }
+
+    #[test]
+    fn config_validates_cycle_start_day() {
+        assert!(UsageConfig { cycle_start_day: 1 }.validate().is_ok());
+        assert!(UsageConfig { cycle_start_day: 31 }.validate().is_ok());
+        assert!(UsageConfig { cycle_start_day: 0 }.validate().is_err());
+        assert!(UsageConfig { cycle_start_day: 32 }.validate().is_err());
+    }
+
+    #[test]
+    fn record_accumulates_totals_and_interfaces() {
+        let mut ledger = UsageLedger::in_memory();
+        let day = date("2024-05-20");
+        let interfaces = vec![("eth0".to_string(), 100, 10), ("wg0".to_string(), 0, 0)];
+        ledger.record(day, (100, 10), &interfaces).unwrap();
+        ledger.record(day, (50, 5), &[("eth0".to_string(), 50, 5)]).unwrap();
+        ledger.record(date("2024-05-21"), (1, 1), &[]).unwrap();
+
+        let next = date("2024-05-21");
+        let total = ledger.total_between(day, next, None).unwrap();
+        assert_eq!((total.rx_bytes, total.tx_bytes, total.total_bytes), (150, 15, 165));
+        assert_eq!(ledger.total_between(day, next, Some("eth0")).unwrap().total_bytes, 165);
+        // 增量为 0 的网卡不写入
+        let rows: i64 = ledger
+            .conn
+            .query_row("SELECT COUNT(*) FROM usage_interface_daily WHERE interface = 'wg0'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 0);
+    }
+
+    #[test]
+    fn summary_uses_week_and_cycle_bounds() {
+        let mut ledger = UsageLedger::in_memory();
+        ledger.record(date("2024-05-14"), (1000, 0), &[]).unwrap();
+        ledger.record(date("2024-05-15"), (200, 0), &[]).unwrap();
+        ledger.record(date("2024-05-20"), (30, 0), &[]).unwrap();
+
+        let summary = ledger.summary(date("2024-05-20"), &UsageConfig { cycle_start_day: 15 }).unwrap();
+        assert_eq!(summary.today.total_bytes, 30);
+        // 2024-05-20 是周一
+        assert_eq!(summary.week.total_bytes, 30);
+        assert_eq!(summary.cycle.total_bytes, 230);
+        assert_eq!((summary.cycle_start.as_str(), summary.cycle_end.as_str()), ("2024-05-15", "2024-06-15"));
+    }
+}
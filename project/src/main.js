@@ -1,5 +1,6 @@
 const { invoke } = window.__TAURI__.core;
 const { getCurrentWindow } = window.__TAURI__.window;
+const { listen } = window.__TAURI__.event;
 
 // 动态调整窗口高度
 async function adjustWindowHeight() {
@@ -22,6 +23,17 @@ function formatSpeed(speed) {
   return (speed / 1024).toFixed(2) + ' MB/s';
 }
 
+// 格式化流量显示
+function formatBytes(bytes) {
+  if (bytes < 1024 * 1024) {
+    return (bytes / 1024).toFixed(1) + ' KB';
+  }
+  if (bytes < 1024 * 1024 * 1024) {
+    return (bytes / 1024 / 1024).toFixed(1) + ' MB';
+  }
+  return (bytes / 1024 / 1024 / 1024).toFixed(2) + ' GB';
+}
+
 // 天气图标映射
 function getWeatherIcon(text) {
   const t = text.toLowerCase();
@@ -53,7 +65,8 @@ async function getIPInfo() {
       ip: ipInfo.ip || '--',
       city: ipInfo.city || '未知',
       country: ipInfo.country || '--',
-      timezone: ipInfo.timezone || ''
+      timezone: ipInfo.timezone || '',
+      asn: ipInfo.asn ? `AS${ipInfo.asn} ${ipInfo.as_org || ''}`.trim() : ''
     };
   } catch (error) {
     console.error('获取IP失败:', error);
@@ -61,11 +74,42 @@ async function getIPInfo() {
       ip: '--',
       city: '未知',
       country: '--',
-      timezone: ''
+      timezone: '',
+      asn: ''
     };
   }
 }
 
+// 分别检查 IPv4 / IPv6 的公网连通性，IPv6 单独显示一行
+async function updateDualStack() {
+  const el = document.getElementById('ipv6Address');
+  try {
+    const stack = await invoke('get_dual_stack');
+    console.log('Dual stack:', stack);
+    const ipv6 = stack.ipv6;
+    el.textContent = ipv6.ip || (ipv6.connected ? '获取失败' : '不可用');
+    el.classList.toggle('unavailable', !ipv6.ip);
+
+    // 悬停显示两个协议的公网地址和各网卡的本地地址
+    const lines = [
+      `IPv4: ${stack.ipv4.ip || '不可用'}${stack.ipv4.provider ? ` (${stack.ipv4.provider})` : ''}`,
+      `IPv6: ${ipv6.ip || '不可用'}${ipv6.provider ? ` (${ipv6.provider})` : ''}`
+    ];
+    for (const iface of stack.interfaces) {
+      const addresses = iface.addresses
+        .filter(a => a.scope !== 'loopback')
+        .map(a => `  ${a.address}/${a.prefix_len} ${a.scope}`);
+      if (addresses.length > 0) {
+        lines.push(`${iface.alias}:`, ...addresses);
+      }
+    }
+    el.title = lines.join('\n');
+  } catch (error) {
+    console.error('获取双栈状态失败:', error);
+    el.textContent = '--';
+  }
+}
+
 // 获取天气信息 - 使用后端命令，接收时区参数
 async function getWeatherInfo(city, timezone) {
   console.log('Fetching weather via backend for:', city, 'timezone:', timezone);
@@ -91,9 +135,12 @@ async function updateWeatherAndIP() {
   try {
     console.log('=== Starting weather and IP update ===');
 
+    updateDualStack();
+
     // 获取 IP 信息
     const ipInfo = await getIPInfo();
     document.getElementById('ipAddress').textContent = ipInfo.ip;
+    document.getElementById('ipAddress').title = ipInfo.asn;
 
     console.log('IP Info:', ipInfo);
 
@@ -142,35 +189,249 @@ async function updateWeatherAndIP() {
   }
 }
 
+// 最近一次显示的样本序号，用于丢弃重复或乱序的推送
+let lastStatsSeq = 0;
+
+// 连接质量的显示文字，按后台给出的稳定标识查找
+const QUALITY_LABELS = {
+  unknown: '检测中...',
+  good: '良好',
+  fair: '一般',
+  poor: '较差',
+};
+
 // 更新 UI
-async function updateStats() {
+function renderStats(stats) {
+  if (stats.seq <= lastStatsSeq) {
+    return;
+  }
+  lastStatsSeq = stats.seq;
+
+  document.getElementById('latency').textContent = `${stats.latency} ms`;
+  document.getElementById('jitter').textContent = `${stats.jitter.toFixed(1)} ms`;
+
+  // P50/P95 显示在数值中，最小/最大值放在悬停提示里
+  const percentilesEl = document.getElementById('latencyPercentiles');
+  percentilesEl.textContent = `${stats.latency_p50.toFixed(1)} / ${stats.latency_p95.toFixed(1)} ms`;
+  percentilesEl.title = `最小 ${stats.latency_min.toFixed(1)} ms，最大 ${stats.latency_max.toFixed(1)} ms`;
+  document.getElementById('download').textContent = formatSpeed(stats.download_speed);
+  document.getElementById('upload').textContent = formatSpeed(stats.upload_speed);
+  document.getElementById('packetLoss').textContent = `${stats.packet_loss.toFixed(1)} %`;
+
+  const mosEl = document.getElementById('mos');
+  mosEl.textContent = stats.quality === 'unknown' ? '--' : stats.mos.toFixed(2);
+  mosEl.title = `R 因子 ${stats.r_factor.toFixed(1)}`;
+
+  // 样式和文字都按质量标识选择，分数由后台给出
+  const statusEl = document.getElementById('status');
+  statusEl.textContent = QUALITY_LABELS[stats.quality] ?? stats.quality;
+  statusEl.title = stats.quality === 'unknown' ? '' : `评分 ${stats.quality_score.toFixed(0)}`;
+  statusEl.className = `stat-value status ${stats.quality}`;
+
+  // 更新时间 - 24小时制，使用后台计算样本的时间
+  document.getElementById('updateTime').textContent = formatTime24(new Date(stats.timestamp));
+}
+
+// 订阅后台推送的统计数据，订阅时先显示当前值
+async function subscribeStats() {
+  try {
+    await listen('network-stats', (event) => renderStats(event.payload));
+    renderStats(await invoke('subscribe_network_stats'));
+  } catch (error) {
+    console.error('Failed to subscribe network stats:', error);
+  }
+}
+
+// 更新流量用量
+async function updateUsage() {
+  try {
+    const usage = await invoke('get_usage_summary');
+
+    document.getElementById('usageToday').textContent = formatBytes(usage.today.total_bytes);
+    document.getElementById('usageToday').title = `本周 ${formatBytes(usage.week.total_bytes)}`;
+
+    const cycleEl = document.getElementById('usageCycle');
+    cycleEl.textContent = formatBytes(usage.cycle.total_bytes);
+    cycleEl.title = `${usage.cycle_start} 至 ${usage.cycle_end}，下载 ${formatBytes(usage.cycle.rx_bytes)}，上传 ${formatBytes(usage.cycle.tx_bytes)}`;
+  } catch (error) {
+    console.error('Failed to get usage summary:', error);
+  }
+}
+
+// 告警横幅：配额提醒和告警规则触发时显示在标题栏下方
+let bannerRule = null;
+
+function showBanner(text, isError, rule) {
+  const el = document.getElementById('alertBanner');
+  el.textContent = text;
+  el.className = isError ? 'alert-banner error' : 'alert-banner';
+  el.hidden = false;
+  bannerRule = rule;
+  adjustWindowHeight();
+}
+
+function hideBanner(rule) {
+  if (bannerRule !== rule) {
+    return;
+  }
+  document.getElementById('alertBanner').hidden = true;
+  bannerRule = null;
+  adjustWindowHeight();
+}
+
+function showQuotaWarning(warning) {
+  let text = `配额「${warning.name}」已用 ${warning.percent.toFixed(0)}%（${formatBytes(warning.used_bytes)} / ${formatBytes(warning.limit_bytes)}）`;
+  if (warning.projected_exhaustion) {
+    text += `，预计 ${new Date(warning.projected_exhaustion).toLocaleString('zh-CN')} 用完`;
+  }
+  showBanner(text, warning.exceeded, null);
+}
+
+function handleAlert(alert) {
+  if (alert.state === 'firing') {
+    showBanner(`${alert.rule}：${alert.message}`, true, alert.rule);
+  } else {
+    hideBanner(alert.rule);
+  }
+}
+
+// 刷新间隔来自设置文件，修改后重新安排定时器
+let usageTimer = null;
+let weatherTimer = null;
+
+function applySettings(settings) {
+  clearInterval(usageTimer);
+  clearInterval(weatherTimer);
+  usageTimer = setInterval(updateUsage, settings.ui.usage_refresh_seconds * 1000);
+  weatherTimer = setInterval(updateWeatherAndIP, settings.ui.weather_refresh_minutes * 60 * 1000);
+}
+
+// 日志文件无法写入时（例如设置的路径不存在）提示
+async function checkLogStatus() {
   try {
-    const stats = await invoke('get_network_stats');
+    const status = await invoke('get_log_status');
+    if (status.error) {
+      showBanner(`日志不可用：${status.error}`, true, null);
+    }
+  } catch (error) {
+    console.error('Failed to get log status:', error);
+  }
+}
 
-    document.getElementById('latency').textContent = `${stats.latency} ms`;
-    document.getElementById('download').textContent = formatSpeed(stats.download_speed);
-    document.getElementById('upload').textContent = formatSpeed(stats.upload_speed);
-    document.getElementById('packetLoss').textContent = `${stats.packet_loss.toFixed(1)} %`;
+async function initSettings() {
+  try {
+    applySettings(await invoke('get_settings'));
+  } catch (error) {
+    console.error('Failed to get settings:', error);
+    applySettings({ ui: { usage_refresh_seconds: 10, weather_refresh_minutes: 10 } });
+  }
+  listen('settings-changed', (event) => {
+    applySettings(event.payload);
+    checkLogStatus();
+  });
+  checkLogStatus();
+}
+
+// 评分标准预设，自定义阈值只能通过配置文件设置
+async function loadQualityPreset() {
+  try {
+    const config = await invoke('get_quality_config');
+    document.getElementById('qualityPreset').value = config.preset;
+  } catch (error) {
+    console.error('Failed to get quality config:', error);
+  }
+}
 
-    const statusEl = document.getElementById('status');
-    statusEl.textContent = stats.status;
-    statusEl.className = 'stat-value status';
+async function initQualityPreset() {
+  const select = document.getElementById('qualityPreset');
+  await loadQualityPreset();
 
-    if (stats.status === '一般') {
-      statusEl.classList.add('warning');
-    } else if (stats.status === '较差') {
-      statusEl.classList.add('error');
+  select.addEventListener('change', async () => {
+    try {
+      await invoke('set_quality_config', { config: { preset: select.value } });
+    } catch (error) {
+      console.error('保存评分标准失败:', error);
     }
+  });
+}
+
+// 网卡筛选：选择哪些网卡计入下载/上传速度
+let interfaceFilter = { mode: 'all' };
+
+function filterUsesList() {
+  return interfaceFilter.mode === 'include' || interfaceFilter.mode === 'exclude';
+}
+
+async function saveInterfaceFilter() {
+  try {
+    await invoke('set_interface_filter', { filter: interfaceFilter });
+  } catch (error) {
+    console.error('保存网卡筛选失败:', error);
+  }
+  renderInterfaceList();
+}
+
+async function renderInterfaceList() {
+  const list = document.getElementById('filterList');
 
-    // 更新时间 - 24小时制
-    const now = new Date();
-    const timeStr = formatTime24(now);
-    document.getElementById('updateTime').textContent = timeStr;
+  try {
+    const interfaces = await invoke('get_interface_stats');
+    const selected = interfaceFilter.interfaces || [];
+
+    list.replaceChildren(...interfaces.filter((iface) => iface.up).map((iface) => {
+      const label = document.createElement('label');
+      label.className = iface.counted ? 'counted' : '';
+      label.title = `${iface.name} (${iface.if_type})`;
+
+      const checkbox = document.createElement('input');
+      checkbox.type = 'checkbox';
+      checkbox.checked = selected.includes(iface.id);
+      checkbox.disabled = !filterUsesList();
+      checkbox.addEventListener('change', () => {
+        const rest = (interfaceFilter.interfaces || []).filter((id) => id !== iface.id);
+        interfaceFilter.interfaces = checkbox.checked ? [...rest, iface.id] : rest;
+        saveInterfaceFilter();
+      });
+
+      label.append(checkbox, iface.alias);
+      return label;
+    }));
   } catch (error) {
-    console.error('Failed to get network stats:', error);
+    console.error('Failed to get interface stats:', error);
   }
 }
 
+async function loadInterfaceFilter() {
+  try {
+    interfaceFilter = await invoke('get_interface_filter');
+  } catch (error) {
+    console.error('Failed to get interface filter:', error);
+  }
+  document.getElementById('filterMode').value = interfaceFilter.mode;
+}
+
+async function initInterfaceFilter() {
+  await loadInterfaceFilter();
+
+  const modeSelect = document.getElementById('filterMode');
+  modeSelect.addEventListener('change', () => {
+    const interfaces = interfaceFilter.interfaces || [];
+    interfaceFilter = { mode: modeSelect.value };
+    if (filterUsesList()) {
+      interfaceFilter.interfaces = interfaces;
+    }
+    saveInterfaceFilter();
+  });
+
+  const details = document.getElementById('interfaceFilter');
+  details.addEventListener('toggle', () => {
+    if (details.open) {
+      renderInterfaceList();
+    }
+    adjustWindowHeight();
+  });
+}
+
 // 检测网络状态变化
 let wasOnline = navigator.onLine;
 let ipRefreshTimer = null;
@@ -207,17 +468,43 @@ window.addEventListener("DOMContentLoaded", async () => {
   }
 
   // 初始更新
-  updateStats();
+  subscribeStats();
+  updateUsage();
+  listen('quota-warning', (event) => showQuotaWarning(event.payload));
+  listen('alert', (event) => handleAlert(event.payload));
+  // 后台检测到公网 IP 变化（如 VPN 断开）时刷新，IPv4 和 IPv6 同时变化时只刷新一次
+  listen('ip-changed', (event) => {
+    console.log('Public IP changed:', event.payload);
+    clearTimeout(ipRefreshTimer);
+    ipRefreshTimer = setTimeout(updateWeatherAndIP, 1000);
+  });
+  // 配置文件被外部修改并重新加载后刷新对应的界面
+  listen('config-changed', async (event) => {
+    switch (event.payload) {
+      case 'interface_filter.json':
+        await loadInterfaceFilter();
+        renderInterfaceList();
+        break;
+      case 'quality.json':
+        loadQualityPreset();
+        break;
+      case 'usage.json':
+      case 'quotas.json':
+        updateUsage();
+        break;
+    }
+  });
+  initInterfaceFilter();
+  initQualityPreset();
   updateWeatherAndIP();
 
   // 等待一下让内容渲染完成，然后调整窗口高度
   setTimeout(adjustWindowHeight, 500);
 
-  // 网络速度：每 1 秒更新一次
-  setInterval(updateStats, 1000);
+  // 网络速度由后台按采样间隔推送 network-stats 事件
 
-  // 天气和 IP 每 10 分钟更新一次
-  setInterval(updateWeatherAndIP, 10 * 60 * 1000);
+  // 流量用量和天气、IP 按设置中的间隔更新（默认 10 秒、10 分钟）
+  initSettings();
 
   // 监听网络状态变化
   window.addEventListener('online', checkNetworkChange);